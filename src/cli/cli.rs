@@ -1,13 +1,52 @@
-use std::path::PathBuf;
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use clap::{Parser, ValueEnum};
+use clap::{ArgAction, Parser, ValueEnum};
 
-use anyhow::{Error, Result};
+use anyhow::{Context, Error, Result};
+use chrono::{DateTime, Datelike, Local, Utc};
+use chrono_tz::Tz;
 use diary_core::{
-    db::{DiaryDB, SortOrder},
-    models::Entry,
+    db::{DiaryDB, DumpFormat, EntryFilter, Period, SortField, SortOrder},
+    models::{Entry, NewEntry},
+    Config, DiaryError,
+};
+use log::{info, warn};
+use regex::RegexBuilder;
+use serde_json::Value;
+
+use super::{
+    color::{self, ColorChoice},
+    date::{self, DateRange},
+    editor,
+    error::{invalid_input, ErrorFormat, Unreachable, EXIT_CODES_HELP},
+    export::{self, Field, OutputFormat, RenderOptions},
+    import, server, template,
 };
 
+const DEFAULT_PAGE: i64 = 1;
+const DEFAULT_PER_PAGE: i64 = 10;
+const DEFAULT_MAX_PER_PAGE: i64 = 100;
+const DEFAULT_MAX_CONTENT_BYTES: usize = 1 << 20;
+const DEFAULT_MAX_CONTENT_LINES: usize = 10_000;
+const DEFAULT_BIND: &str = "127.0.0.1:3000";
+const DEFAULT_EXPORT_STATE_FILE: &str = "diary-export.state";
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+const DEFAULT_POLL_INTERVAL_MS: u64 = 2000;
+const DEFAULT_FUZZY_THRESHOLD: f64 = 0.3;
+const SUMMARY_BAR_WIDTH: i64 = 50;
+/// `diary init` writes config.ini.sample with this url replaced by one in
+/// the platform data directory, so the two can't drift apart.
+const CONFIG_SAMPLE: &str = include_str!("../../config.ini.sample");
+const SAMPLE_DB_URL: &str = "sqlite:///home/me/.local/share/diary/diary.db";
+const DB_FILE: &str = "diary.db";
+
 #[derive(Debug, Clone, ValueEnum)]
 pub enum Mode {
     #[value(name = "c")]
@@ -20,10 +59,77 @@ pub enum Mode {
     Delete,
     #[value(name = "a")]
     DumpAll,
+    #[value(name = "restore")]
+    Restore,
+    #[value(name = "purge")]
+    Purge,
+    #[value(name = "s")]
+    Stats,
+    #[value(name = "i")]
+    Import,
+    #[value(name = "p")]
+    Pin,
+    #[value(name = "unpin")]
+    Unpin,
+    #[value(name = "h")]
+    History,
+    #[value(name = "serve")]
+    Serve,
+    #[value(name = "dup")]
+    Duplicates,
+    #[value(name = "merge")]
+    Merge,
+    #[value(name = "ping")]
+    Ping,
+    #[value(name = "optimize")]
+    Optimize,
+    #[value(name = "append")]
+    Append,
+    #[value(name = "templates")]
+    Templates,
+    #[value(name = "categories")]
+    Categories,
+    #[value(name = "summary")]
+    Summary,
+    #[value(name = "prune")]
+    Prune,
+    #[value(name = "onthisday")]
+    OnThisDay,
+    #[value(name = "init")]
+    Init,
+    #[value(name = "link")]
+    Link,
+    #[value(name = "unlink")]
+    Unlink,
+}
+
+impl Mode {
+    /// Whether the mode can change the database. Serve is included because
+    /// its API accepts writes.
+    fn writes(&self) -> bool {
+        matches!(
+            self,
+            Mode::Create
+                | Mode::Update
+                | Mode::Delete
+                | Mode::Restore
+                | Mode::Purge
+                | Mode::Import
+                | Mode::Pin
+                | Mode::Unpin
+                | Mode::Serve
+                | Mode::Merge
+                | Mode::Optimize
+                | Mode::Append
+                | Mode::Prune
+                | Mode::Link
+                | Mode::Unlink
+        )
+    }
 }
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = EXIT_CODES_HELP)]
 pub struct Args {
     // #[arg(short, long)]
     #[arg(value_enum)]
@@ -32,119 +138,2408 @@ pub struct Args {
     #[arg(short, long)]
     pub id: Option<i64>,
 
+    /// Keep running and print new entries as they are created, until
+    /// interrupted.
+    #[arg(long, conflicts_with_all = ["id", "ids", "cursor", "all"])]
+    pub follow: bool,
+
+    /// How often --follow checks for new entries.
+    #[arg(long, default_value_t = DEFAULT_POLL_INTERVAL_MS)]
+    pub poll_interval_ms: u64,
+
+    /// Bucket size of the summary: day, week or month.
+    #[arg(long, value_enum, default_value_t = Period::Day)]
+    pub period: Period,
+
+    /// Day recalled by onthisday, MM-DD; today by default.
+    #[arg(long, value_parser = date::parse_month_day)]
+    pub day: Option<(u32, u32)>,
+
+    /// Log the database's query plan for a paged read, e.g. to check that
+    /// the created_at index is used.
+    #[arg(long, conflicts_with_all = ["id", "ids", "cursor", "search", "regex", "fuzzy", "date", "follow"])]
+    pub explain: bool,
+
+    /// Write read results to this file instead of stdout.
+    #[arg(long, conflicts_with = "follow")]
+    pub output: Option<String>,
+
+    /// Overwrite the --output file, or the config file written by init, if
+    /// it already exists.
+    #[arg(long)]
+    pub force: bool,
+
+    /// Read only the oldest entry matching the filters.
+    #[arg(long, conflicts_with_all = ["id", "ids", "page", "per_page", "cursor", "all", "last"])]
+    pub first: bool,
+
+    /// Read only the newest entry matching the filters.
+    #[arg(long, conflicts_with_all = ["id", "ids", "page", "per_page", "cursor", "all"])]
+    pub last: bool,
+
+    /// Read N random entries, one when N is left out, e.g. to look back at
+    /// a past entry. Honors --date and the row filters such as --pinned.
+    #[arg(
+        long,
+        num_args = 0..=1,
+        default_missing_value = "1",
+        conflicts_with_all = [
+            "id", "ids", "page", "per_page", "cursor", "all", "first", "last", "since_id",
+            "search", "regex", "fuzzy", "sort_by",
+        ]
+    )]
+    pub random: Option<i64>,
+
+    /// Entry that link and unlink connect --id to.
+    #[arg(long, requires = "id")]
+    pub to: Option<i64>,
+
+    /// Read the entries linked from --id instead of the entry itself.
+    #[arg(long, requires = "id")]
+    pub linked: bool,
+
+    /// Read several entries at once, e.g. --ids 1,3,5.
+    #[arg(long, value_delimiter = ',', conflicts_with = "id")]
+    pub ids: Vec<i64>,
+
     #[arg(short = 't', long)]
     pub content: Option<String>,
 
+    /// Read the content from a file, or from stdin when "-" is given.
+    #[arg(long)]
+    pub content_file: Option<String>,
+
     #[arg(short, long)]
     pub pinned: Option<bool>,
 
-    #[arg(short, long, default_value_t = String::from("config.ini"))]
-    pub config: String,
+    /// Star a created or updated entry, or only read starred (true) or
+    /// unstarred (false) entries. Independent of --pinned.
+    #[arg(long)]
+    pub starred: Option<bool>,
+
+    /// Config file, by default diary/config.ini in the user config directory
+    /// (e.g. ~/.config on Linux).
+    #[arg(short, long)]
+    pub config: Option<String>,
 
+    /// Use the [Database.<name>] section of the config file.
     #[arg(long)]
-    pub per_page: Option<i64>,
+    pub profile: Option<String>,
 
+    /// Fall back to the default database when the config file is missing.
     #[arg(long)]
+    pub use_default_config: bool,
+
+    #[arg(long)]
+    pub per_page: Option<i64>,
+
+    #[arg(long, conflicts_with = "cursor")]
     pub page: Option<i64>,
 
-    #[arg(value_enum)]
+    #[arg(long)]
+    pub cursor: Option<i64>,
+
+    /// Read the entries written in a range of local days: today, yesterday,
+    /// thismonth, Nd, Nw, Nm or YYYY-MM-DD.
+    #[arg(long, value_parser = date::parse, conflicts_with_all = ["page", "cursor"])]
+    pub date: Option<DateRange>,
+
+    /// Only read entries last updated at or after this time: an RFC 3339
+    /// timestamp or a --date expression such as 7d. Entries that were never
+    /// updated don't match.
+    #[arg(long, value_parser = date::parse_bound)]
+    pub updated_after: Option<DateTime<Utc>>,
+
+    /// Only read entries last updated before this time, in the same forms
+    /// as --updated-after.
+    #[arg(long, value_parser = date::parse_bound)]
+    pub updated_before: Option<DateTime<Utc>>,
+
+    /// JSON object of metadata stored with a created or updated entry, e.g.
+    /// '{"mood":5}'.
+    #[arg(long, value_parser = parse_metadata)]
+    pub meta: Option<Value>,
+
+    /// Only read entries whose metadata has this key, compared as text with
+    /// --meta-value.
+    #[arg(long, requires = "meta_value")]
+    pub meta_key: Option<String>,
+
+    #[arg(long, requires = "meta_key")]
+    pub meta_value: Option<String>,
+
+    /// Category of a created or updated entry, or the category to read.
+    #[arg(long)]
+    pub category: Option<String>,
+
+    /// Add this text as a new line at the end of the entry instead of
+    /// replacing its content.
+    #[arg(long, requires = "id", conflicts_with_all = ["content", "content_file"])]
+    pub append: Option<String>,
+
+    /// Keep the entry's updated_at unchanged, e.g. when fixing a typo.
+    #[arg(long)]
+    pub no_touch: bool,
+
+    /// Fail the update with a conflict if the entry was changed since this
+    /// version was read.
+    #[arg(long, requires = "id")]
+    pub expected_version: Option<i64>,
+
+    /// Start a new entry from a saved template. {{date}} and {{time}} are
+    /// replaced with the current local date and time.
+    #[arg(long)]
+    pub template: Option<String>,
+
+    /// Append to the most recent of several entries written today.
+    #[arg(long)]
+    pub latest: bool,
+
+    /// Unique key for a create. Repeating a create with the same key returns
+    /// the existing entry instead of adding a duplicate.
+    #[arg(long)]
+    pub idempotency_key: Option<String>,
+
+    /// Backdate a new entry to an RFC 3339 timestamp, e.g. 2019-05-04T21:30:00Z.
+    #[arg(long, value_parser = date::parse_timestamp)]
+    pub created_at: Option<DateTime<Utc>>,
+
+    /// Read every matching entry instead of a single page.
+    #[arg(long, conflicts_with_all = ["page", "per_page", "cursor"])]
+    pub all: bool,
+
+    /// Sort order (asc or desc), or the entry content for create, update and
+    /// append.
+    #[arg(value_name = "SORT|CONTENT")]
+    pub positional: Option<String>,
+
+    /// Set from the positional argument by `resolve_positional`.
+    #[arg(skip)]
     pub sort: Option<SortOrder>,
 
+    /// Column to sort by, in the direction given by the sort order.
+    #[arg(long, value_enum)]
+    pub sort_by: Option<SortField>,
+
     #[arg(long)]
     pub substr: Option<String>,
 
+    /// Match --substr against whole words only.
+    #[arg(long, requires = "substr")]
+    pub whole_word: bool,
+
+    /// Full-text search, ordered by relevance.
+    #[arg(long, conflicts_with = "substr")]
+    pub search: Option<String>,
+
+    /// Regular expression search. SQLite has no built-in regex support, so
+    /// there the rows are loaded and matched in Rust.
+    #[arg(long, conflicts_with_all = ["substr", "search"])]
+    pub regex: Option<String>,
+
+    /// Only show the lines of each entry that match --substr or --regex,
+    /// with this many lines before and after each match.
+    #[arg(long, conflicts_with_all = ["id", "ids"])]
+    pub context: Option<usize>,
+
+    /// Typo-tolerant search, best match first. Entries scoring below
+    /// fuzzy_threshold from the config (0.3 by default) are left out.
+    #[arg(long, conflicts_with_all = ["substr", "search", "regex"])]
+    pub fuzzy: Option<String>,
+
     #[arg(long)]
     pub path: Option<String>,
+
+    /// Import every valid row and skip the invalid ones instead of stopping
+    /// at the first error.
+    #[arg(long)]
+    pub continue_on_error: bool,
+
+    /// Write the line of each row skipped by --continue-on-error and why to
+    /// this file.
+    #[arg(long, requires = "continue_on_error")]
+    pub error_report: Option<String>,
+
+    #[arg(long = "tag")]
+    pub tags: Vec<String>,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Shorthand for --format porcelain: one tab-separated line per entry
+    /// in a format that stays stable across versions.
+    #[arg(long, conflicts_with = "format")]
+    pub porcelain: bool,
+
+    /// Only output these fields, e.g. --fields id,created_at,pinned. Entries
+    /// are still read in full; the other fields are left out when printing.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub fields: Vec<Field>,
+
+    /// Show the whole content of every listed entry even when preview_chars
+    /// is set in the config file.
+    #[arg(long)]
+    pub full: bool,
+
+    /// One line per entry in text output: id, date, a pin marker and the
+    /// start of the content.
+    #[arg(long, conflicts_with_all = ["format", "porcelain", "fields", "context"])]
+    pub compact: bool,
+
+    /// Colorize text output. `auto` colors only on a terminal and honors
+    /// NO_COLOR.
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// IANA timezone for displayed timestamps, e.g. Europe/Berlin. Overrides
+    /// display_timezone from the config file; the local timezone is the default.
+    #[arg(long)]
+    pub tz: Option<Tz>,
+
+    /// Show timestamps in UTC, e.g. to compare output across machines or
+    /// with raw database values. Same as --tz UTC.
+    #[arg(long, conflicts_with = "tz")]
+    pub utc: bool,
+
+    /// Also read soft-deleted entries, both in listings and with --id.
+    #[arg(long)]
+    pub include_deleted: bool,
+
+    /// Only read or dump entries with an id greater than this one. Reads
+    /// go oldest first, so a sync client can page forward from its last id.
+    #[arg(long, conflicts_with_all = ["id", "ids", "cursor", "page", "date", "follow"])]
+    pub since_id: Option<i64>,
+
+    /// Only dump entries created since the previous incremental dump.
+    #[arg(long, conflicts_with = "since_id")]
+    pub since_last: bool,
+
+    /// File recording the last exported id, overrides export_state_file.
+    #[arg(long)]
+    pub state_file: Option<String>,
+
+    #[arg(long)]
+    pub older_than_days: Option<i64>,
+
+    /// Let prune remove pinned entries too.
+    #[arg(long)]
+    pub include_pinned: bool,
+
+    /// Report what create, update, delete, import and init would change
+    /// without writing anything.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Refuse every mode that changes the database, e.g. to browse the
+    /// diary on a shared machine. Overrides read_only from the config file.
+    #[arg(long)]
+    pub read_only: bool,
+
+    /// Show more log output, -vv for everything.
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Report a failed operation on stderr as text or as a JSON object.
+    #[arg(long, value_enum, default_value_t = ErrorFormat::Text)]
+    pub error_format: ErrorFormat,
+
+    /// Only log warnings and errors.
+    #[arg(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Address the HTTP server listens on.
+    #[arg(long, default_value_t = String::from(DEFAULT_BIND))]
+    pub bind: String,
+
+    /// Database URL whose entries are merged into the configured database.
+    #[arg(long)]
+    pub from_url: Option<String>,
+
+    /// Permanently delete the merged entries from the source database, all
+    /// at once, after they are committed to the destination.
+    #[arg(long, requires = "from_url")]
+    pub delete_source: bool,
 }
 
-fn print_entries(entries: Vec<Entry>) {
-    println!("\nFound {} entries.\n", entries.len());
-    let str = entries
-        .into_iter()
-        .map(|entry| entry.to_string())
-        .collect::<Vec<String>>()
-        .join("\n\n");
-    println!("{}", str);
+/// Upper bound on the page size of a single read.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct PageLimit {
+    default: Option<i64>,
+    max: i64,
+    clamp: bool,
 }
 
-pub async fn create_entry(db: &DiaryDB, args: Args) -> Result<()> {
-    if args.content.is_none() {
-        return Err(Error::msg("Content must be provided for this operation"));
+impl PageLimit {
+    pub(super) fn from_config(config: &Config) -> Self {
+        PageLimit {
+            default: config.default_per_page,
+            max: config.max_per_page.unwrap_or(DEFAULT_MAX_PER_PAGE),
+            clamp: config.clamp_per_page.unwrap_or(true),
+        }
     }
 
-    db.db
-        .create_entry(args.content.unwrap(), args.pinned.unwrap_or(false))
-        .await?;
+    /// Resolves the requested page size, falling back to the default. Sizes
+    /// above the maximum are clamped or rejected depending on the config.
+    pub(super) fn resolve(&self, per_page: Option<i64>) -> Result<i64> {
+        let per_page = setting(per_page, self.default, DEFAULT_PER_PAGE);
 
-    Ok(())
+        if per_page < 1 {
+            return Err(invalid_input("--per-page must be at least 1."));
+        }
+
+        if per_page > self.max {
+            if self.clamp {
+                return Ok(self.max);
+            }
+            return Err(invalid_input(format!(
+                "--per-page must not exceed {}.",
+                self.max
+            )));
+        }
+
+        Ok(per_page)
+    }
 }
 
-pub async fn read_entry(db: &DiaryDB, args: Args) -> Result<()> {
-    if let Some(id) = args.id {
-        let entry = db.db.read_entry(id).await?;
-        println!("{}", entry);
+/// Rejects page numbers below the first page.
+pub(super) fn check_page(page: Option<i64>) -> Result<()> {
+    match page {
+        Some(page) if page < 1 => Err(invalid_input("--page must be at least 1.")),
+        _ => Ok(()),
+    }
+}
 
-        return Ok(());
+/// Upper bounds on the size of an entry's content.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ContentLimits {
+    max_bytes: usize,
+    max_lines: usize,
+}
+
+impl ContentLimits {
+    pub(super) fn from_config(config: &Config) -> Self {
+        ContentLimits {
+            max_bytes: config
+                .max_content_bytes
+                .unwrap_or(DEFAULT_MAX_CONTENT_BYTES),
+            max_lines: config
+                .max_content_lines
+                .unwrap_or(DEFAULT_MAX_CONTENT_LINES),
+        }
     }
 
-    let entries = db
-        .db
-        .read_entries(
-            args.page,
-            args.per_page,
-            args.sort,
-            args.pinned,
-            args.substr,
-        )
-        .await?;
-    print_entries(entries);
+    /// Rejects content above either limit, naming the limit and the excess.
+    pub(super) fn check(&self, content: &str) -> Result<()> {
+        let bytes = content.len();
+        if bytes > self.max_bytes {
+            return Err(invalid_input(format!(
+                "Content is {} bytes, {} over the max_content_bytes limit of {}.",
+                bytes,
+                bytes - self.max_bytes,
+                self.max_bytes
+            )));
+        }
+
+        let lines = content.lines().count();
+        if lines > self.max_lines {
+            return Err(invalid_input(format!(
+                "Content has {} lines, {} over the max_content_lines limit of {}.",
+                lines,
+                lines - self.max_lines,
+                self.max_lines
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Resolves a setting from its command line flag, then the config file,
+/// then the built-in default.
+fn setting<T>(flag: Option<T>, config: Option<T>, default: T) -> T {
+    flag.or(config).unwrap_or(default)
+}
+
+/// The row filters of a read, shared by the page, its count and its query
+/// plan so they always agree on the WHERE clause.
+fn entry_filter(args: &Args) -> EntryFilter {
+    EntryFilter {
+        pinned: args.pinned,
+        starred: args.starred,
+        substr: args.substr.clone(),
+        whole_word: args.whole_word,
+        tags: tags_arg(args.tags.clone()),
+        // clap only accepts --meta-key and --meta-value together.
+        meta: args.meta_key.clone().zip(args.meta_value.clone()),
+        category: args.category.clone(),
+        updated_after: args.updated_after,
+        updated_before: args.updated_before,
+        include_deleted: args.include_deleted,
+    }
+}
+
+fn tags_arg(tags: Vec<String>) -> Option<Vec<String>> {
+    if tags.is_empty() {
+        None
+    } else {
+        Some(tags)
+    }
+}
+
+fn resolve_content(
+    content: Option<String>,
+    content_file: Option<String>,
+) -> Result<Option<String>> {
+    match (content, content_file) {
+        (Some(_), Some(_)) => Err(invalid_input(
+            "Only one of --content and --content-file can be provided.",
+        )),
+        (Some(content), None) => Ok(Some(content)),
+        (None, Some(path)) if path == "-" => {
+            let mut content = String::new();
+            io::stdin()
+                .read_to_string(&mut content)
+                .context("Failed to read content from stdin")?;
+            Ok(Some(content))
+        }
+        (None, Some(path)) => {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read content from {}", path))?;
+            Ok(Some(content))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+/// Trims surrounding whitespace and rejects content that is empty afterwards.
+pub(super) fn normalize_content(content: String) -> Result<String> {
+    let trimmed = content.trim();
+    if trimmed.is_empty() {
+        return Err(invalid_input(
+            "Content must not be empty or whitespace only.",
+        ));
+    }
+
+    Ok(trimmed.to_string())
+}
+
+/// Applies the content checks of create to an entry read from elsewhere,
+/// such as an import file or a merged database.
+fn check_new_entry(entry: NewEntry, limits: &ContentLimits) -> Result<NewEntry> {
+    let content = normalize_content(entry.content)?;
+    limits.check(&content)?;
+
+    Ok(NewEntry { content, ..entry })
+}
+
+fn parse_metadata(value: &str) -> Result<Value> {
+    let metadata: Value = serde_json::from_str(value).context("Invalid metadata JSON")?;
+    if !metadata.is_object() {
+        return Err(invalid_input("Metadata must be a JSON object"));
+    }
+
+    Ok(metadata)
+}
+
+fn render_options(args: &Args, config: &Config) -> Result<RenderOptions> {
+    let tz =
+        match (args.tz, &config.display_timezone) {
+            (Some(tz), _) => Some(tz),
+            (None, _) if args.utc => Some(Tz::UTC),
+            (None, Some(name)) => Some(name.parse::<Tz>().map_err(|e| {
+                invalid_input(format!("Invalid display_timezone '{}': {}", name, e))
+            })?),
+            (None, None) => None,
+        };
+
+    // Only plain substring filters are highlighted; --regex patterns are
+    // validated before use, so an invalid one never gets here.
+    let highlight = match (&args.substr, &args.regex) {
+        (Some(substr), _) => RegexBuilder::new(&regex::escape(substr))
+            .case_insensitive(true)
+            .build()
+            .ok(),
+        (None, Some(pattern)) => RegexBuilder::new(pattern)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .build()
+            .ok(),
+        (None, None) => None,
+    };
+
+    if args.context.is_some() && args.substr.is_none() && args.regex.is_none() {
+        return Err(invalid_input("--context needs --substr or --regex."));
+    }
+
+    Ok(RenderOptions {
+        format: if args.porcelain {
+            OutputFormat::Porcelain
+        } else {
+            args.format
+        },
+        tz,
+        color: color::enabled(args.color),
+        highlight,
+        context: args.context,
+        compact: args.compact,
+        preview_chars: if args.full {
+            None
+        } else {
+            config.preview_chars
+        },
+        fields: args.fields.clone(),
+    })
+}
+
+fn print_entries(
+    out: &mut dyn Write,
+    mut entries: Vec<Entry>,
+    options: &RenderOptions,
+) -> Result<()> {
+    if options.compact {
+        return print_compact_entries(out, &entries, options);
+    }
+
+    writeln!(out, "\nFound {} entries.\n", entries.len())?;
+
+    if let (Some(context), Some(pattern)) = (options.context, &options.highlight) {
+        for entry in &mut entries {
+            entry.content = export::excerpt(&entry.content, pattern, context);
+        }
+    }
+    if let Some(max) = options.preview_chars {
+        for entry in &mut entries {
+            let (preview, truncated) = export::truncate(&entry.content, max);
+            if truncated {
+                entry.content = format!("{}… (truncated, use --id {} for full)", preview, entry.id);
+            }
+        }
+    }
+    writeln!(out, "{}", export::render_entries(&entries, options)?)?;
 
     Ok(())
 }
 
-pub async fn delete_entry(db: &DiaryDB, args: Args) -> Result<()> {
-    if args.id.is_none() {
-        return Err(Error::msg("Entry ID must be provided for this operation."));
+/// Prints `--compact` text output, one line per entry.
+fn print_compact_entries(
+    out: &mut dyn Write,
+    entries: &[Entry],
+    options: &RenderOptions,
+) -> Result<()> {
+    writeln!(out, "\nFound {} entries.\n", entries.len())?;
+    for entry in entries {
+        writeln!(out, "{}", export::compact_entry(entry, options))?;
     }
 
-    db.db.delete_entry(args.id.unwrap()).await?;
+    Ok(())
+}
+
+/// Prints a list of entries in the requested format.
+fn output_entries(out: &mut dyn Write, entries: Vec<Entry>, options: &RenderOptions) -> Result<()> {
+    match options.format {
+        OutputFormat::Text => print_entries(out, entries, options)?,
+        _ => writeln!(out, "{}", export::render_entries(&entries, options)?)?,
+    }
 
     Ok(())
 }
 
-pub async fn update_entry(db: &DiaryDB, args: Args) -> Result<()> {
-    if args.id.is_none() {
-        return Err(Error::msg("Entry ID must be provided for this operation."));
+/// Prints a list like `output_entries`, but explains an empty text listing
+/// instead of reporting zero entries.
+async fn output_list(
+    db: &DiaryDB,
+    out: &mut dyn Write,
+    entries: Vec<Entry>,
+    options: &RenderOptions,
+) -> Result<()> {
+    if entries.is_empty() && options.format == OutputFormat::Text {
+        return print_no_entries(db, out).await;
     }
 
-    db.db
-        .update_entry(args.id.unwrap(), args.content, args.pinned)
-        .await?;
+    output_entries(out, entries, options)
+}
+
+/// Prints ranked search results. JSON output carries each entry's score in
+/// a `score` field, the other formats list the entries in rank order.
+fn output_scored_entries(
+    out: &mut dyn Write,
+    matches: Vec<(Entry, f64)>,
+    options: &RenderOptions,
+) -> Result<()> {
+    let scored = || {
+        matches
+            .iter()
+            .map(|(entry, score)| {
+                let mut json = export::entry_json(entry, &options.fields)?;
+                json["score"] = (*score).into();
+                Ok(json)
+            })
+            .collect::<Result<Vec<Value>>>()
+    };
+
+    match options.format {
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&scored()?)?)?,
+        OutputFormat::JsonLines => {
+            for json in scored()? {
+                writeln!(out, "{}", json)?;
+            }
+        }
+        _ => {
+            let entries = matches.into_iter().map(|(entry, _)| entry).collect();
+            output_entries(out, entries, options)?;
+        }
+    }
 
     Ok(())
 }
 
-pub async fn dump_entries(db: &DiaryDB, args: Args) -> Result<()> {
-    match args.path {
-        Some(p) => {
-            db.db.dump_entries(Some(&PathBuf::from(p))).await?;
+/// Opens the `--output` file for writing, creating its parent directories.
+/// An existing file is only overwritten with `--force`.
+fn open_output(path: &str, force: bool) -> Result<File> {
+    let path = Path::new(path);
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    options.open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => invalid_input(format!(
+            "{} already exists, pass --force to overwrite it.",
+            path.display()
+        )),
+        _ => Error::new(e).context(format!("Failed to open {}", path.display())),
+    })
+}
+
+/// Reads an entry, failing with `DiaryError::NotFound` when it doesn't exist.
+pub(super) async fn require_entry(db: &DiaryDB, id: i64) -> Result<Entry> {
+    db.db
+        .read_entry(id)
+        .await?
+        .ok_or_else(|| DiaryError::NotFound { id }.into())
+}
+
+/// Like `require_entry`, but also finds entries that were soft-deleted.
+async fn require_entry_including_deleted(db: &DiaryDB, id: i64) -> Result<Entry> {
+    db.db
+        .read_entry_including_deleted(id)
+        .await?
+        .ok_or_else(|| DiaryError::NotFound { id }.into())
+}
+
+async fn fetch_all_entries(db: &DiaryDB) -> Result<Vec<Entry>> {
+    db.db
+        .read_entries(None, None, None, None, &EntryFilter::default())
+        .await
+}
+
+pub async fn create_entry(
+    db: &DiaryDB,
+    args: Args,
+    config: &Config,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let options = render_options(&args, config)?;
+    let mut content = resolve_content(args.content, args.content_file)?;
+    let initial = match &args.template {
+        Some(_) if content.is_some() => {
+            return Err(invalid_input("--template can't be combined with content."))
+        }
+        Some(name) => template::load(name)?,
+        None => String::new(),
+    };
+
+    if content.is_none() && io::stdin().is_terminal() {
+        content = editor::compose(&initial)?;
+        if content.is_none() {
+            info!("Entry is empty or unchanged, nothing was created.");
+            return Ok(());
+        }
+    } else if content.is_none() && args.template.is_some() {
+        content = Some(initial);
+    }
+
+    if content.is_none() {
+        return Err(invalid_input("Content must be provided for this operation"));
+    }
+
+    let content = normalize_content(content.unwrap())?;
+    ContentLimits::from_config(config).check(&content)?;
+    let pinned = setting(args.pinned, config.default_pinned, false);
+    let starred = args.starred.unwrap_or(false);
+
+    if args.dry_run {
+        let created_at = args
+            .created_at
+            .map(|time| format!(" dated {}", time.to_rfc3339()))
+            .unwrap_or_default();
+        writeln!(
+            out,
+            "Dry run: would create {}entry{}:\n{}",
+            if pinned { "pinned " } else { "" },
+            created_at,
+            content
+        )?;
+        return Ok(());
+    }
+
+    let tags = tags_arg(args.tags);
+    let entry = match args.idempotency_key {
+        Some(key) => {
+            let (entry, created) = db
+                .db
+                .create_entry_idempotent(
+                    key,
+                    content,
+                    pinned,
+                    starred,
+                    tags,
+                    args.created_at,
+                    args.meta,
+                    args.category,
+                )
+                .await?;
+            if !created {
+                info!(
+                    "Entry {} already exists for this key, nothing was created.",
+                    entry.id
+                );
+            }
+            entry
         }
         None => {
-            db.db.dump_entries(None).await?;
+            db.db
+                .create_entry(
+                    content,
+                    pinned,
+                    starred,
+                    tags,
+                    args.created_at,
+                    args.meta,
+                    args.category,
+                )
+                .await?
         }
     };
 
+    print_written_entry(&entry, "Created", &options, out)
+}
+
+/// Reports an entry that was just written: its id in text mode, the whole
+/// entry in the structured formats so scripts can pick up the id.
+fn print_written_entry(
+    entry: &Entry,
+    action: &str,
+    options: &RenderOptions,
+    out: &mut dyn Write,
+) -> Result<()> {
+    match options.format {
+        OutputFormat::Text => info!("{} entry {}.", action, entry.id),
+        _ => writeln!(out, "{}", export::render_entry(entry, options)?)?,
+    }
+
     Ok(())
 }
 
-pub async fn process_args(db: &DiaryDB, args: Args) -> Result<()> {
-    match args.mode {
-        Mode::Create => create_entry(db, args).await?,
-        Mode::Read => read_entry(db, args).await?,
-        Mode::Delete => delete_entry(db, args).await?,
-        Mode::Update => update_entry(db, args).await?,
-        Mode::DumpAll => dump_entries(db, args).await?,
+/// Rejects patterns that don't compile or would compile to an oversized
+/// program, before they reach the database.
+fn check_regex(pattern: &str) -> Result<()> {
+    RegexBuilder::new(pattern)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| invalid_input(format!("Invalid regex: {}", e)))?;
+
+    Ok(())
+}
+
+pub async fn read_entry(
+    db: &DiaryDB,
+    args: Args,
+    config: &Config,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut options = render_options(&args, config)?;
+
+    if args.follow {
+        return follow_entries(db, args.poll_interval_ms, &options, out).await;
+    }
+
+    match args.output.clone() {
+        Some(path) => {
+            options.color = false;
+            let mut out = BufWriter::new(open_output(&path, args.force)?);
+            write_entries(db, args, config, &options, &mut out).await?;
+            out.flush()
+                .with_context(|| format!("Failed to write {}", path))?;
+        }
+        None => write_entries(db, args, config, &options, out).await?,
     }
 
     Ok(())
 }
+
+async fn write_entries(
+    db: &DiaryDB,
+    args: Args,
+    config: &Config,
+    options: &RenderOptions,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let page_limit = PageLimit::from_config(config);
+
+    if let Some(id) = args.id.filter(|_| args.linked) {
+        require_entry(db, id).await?;
+        let entries = db.db.read_linked(id).await?;
+        return output_list(db, out, entries, options).await;
+    }
+
+    if let Some(id) = args.id {
+        let entry = if args.include_deleted {
+            require_entry_including_deleted(db, id).await?
+        } else {
+            require_entry(db, id).await?
+        };
+        writeln!(out, "{}", export::render_entry(&entry, options)?)?;
+
+        return Ok(());
+    }
+
+    if !args.ids.is_empty() {
+        return read_entries_by_ids(db, args.ids, options, out).await;
+    }
+
+    if args.first || args.last {
+        let sort = if args.first {
+            SortOrder::Asc
+        } else {
+            SortOrder::Desc
+        };
+        let entries = db
+            .db
+            .read_entries(
+                Some(1),
+                Some(1),
+                Some(sort),
+                args.sort_by,
+                &entry_filter(&args),
+            )
+            .await?;
+
+        match entries.first() {
+            Some(entry) => writeln!(out, "{}", export::render_entry(entry, options)?)?,
+            None if options.format == OutputFormat::Text => print_no_entries(db, out).await?,
+            None => info!("No entries found."),
+        }
+        return Ok(());
+    }
+
+    if let Some(count) = args.random {
+        if count < 1 {
+            return Err(invalid_input("--random needs a count of at least 1."));
+        }
+        if args.sort.is_some() {
+            return Err(invalid_input(
+                "--random can't be combined with a sort order.",
+            ));
+        }
+        let created = args.date.map(|range| (range.start, range.end));
+        let entries = db
+            .db
+            .read_random(count, &entry_filter(&args), created)
+            .await?;
+        return output_list(db, out, entries, options).await;
+    }
+
+    if let Some(since_id) = args.since_id {
+        // Ids only grow, so paging forward by id never skips or repeats an
+        // entry, unlike paging by timestamp.
+        let per_page = page_limit.resolve(args.per_page)?;
+        let args = Args {
+            cursor: Some(since_id),
+            sort: Some(SortOrder::Asc),
+            ..args
+        };
+        return read_entries_after(db, args, per_page, options, out).await;
+    }
+
+    if args.cursor.is_some() {
+        let per_page = page_limit.resolve(args.per_page)?;
+        return read_entries_after(db, args, per_page, options, out).await;
+    }
+
+    if let Some(query) = args.search {
+        let entries = db
+            .db
+            .search_entries(query, page_limit.resolve(args.per_page)?)
+            .await?;
+        return output_list(db, out, entries, options).await;
+    }
+
+    if let Some(pattern) = args.regex {
+        check_regex(&pattern)?;
+        let entries = db
+            .db
+            .search_entries_regex(pattern, page_limit.resolve(args.per_page)?)
+            .await?;
+        return output_list(db, out, entries, options).await;
+    }
+
+    if let Some(term) = args.fuzzy {
+        let threshold = config.fuzzy_threshold.unwrap_or(DEFAULT_FUZZY_THRESHOLD);
+        let matches = db
+            .db
+            .search_entries_fuzzy(term, threshold, page_limit.resolve(args.per_page)?)
+            .await?;
+        if matches.is_empty() && options.format == OutputFormat::Text {
+            return print_no_entries(db, out).await;
+        }
+        return output_scored_entries(out, matches, options);
+    }
+
+    if let Some(range) = args.date {
+        let entries = db
+            .db
+            .read_entries_between(
+                range.start,
+                range.end,
+                args.sort,
+                args.sort_by,
+                &entry_filter(&args),
+            )
+            .await?;
+        return output_list(db, out, entries, options).await;
+    }
+
+    let filter = entry_filter(&args);
+    let total = db.db.count_entries(&filter).await?;
+
+    check_page(args.page)?;
+
+    // A missing per_page means no LIMIT at all, so resolve the default here.
+    let (page, per_page) = if args.all {
+        (None, None)
+    } else {
+        (args.page, Some(page_limit.resolve(args.per_page)?))
+    };
+
+    if args.explain {
+        let plan = db
+            .db
+            .explain_read_entries(page, per_page, args.sort, args.sort_by, &filter)
+            .await?;
+        info!("Query plan:\n{}", plan.join("\n"));
+    }
+
+    let entries = db
+        .db
+        .read_entries(page, per_page, args.sort, args.sort_by, &filter)
+        .await?;
+
+    if options.format != OutputFormat::Text {
+        return output_entries(out, entries, options);
+    }
+
+    if entries.is_empty() {
+        if total > 0 {
+            writeln!(
+                out,
+                "\nPage {} is past the last page of {} matching entries.",
+                page.unwrap_or(DEFAULT_PAGE),
+                total
+            )?;
+            return Ok(());
+        }
+        return print_no_entries(db, out).await;
+    }
+
+    print_entries(out, entries, options)?;
+
+    if let Some(per_page) = per_page {
+        let pages = ((total + per_page - 1) / per_page).max(1);
+        writeln!(
+            out,
+            "\nShowing page {} of {} ({} per page).",
+            page.unwrap_or(DEFAULT_PAGE),
+            pages,
+            per_page
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Polls for entries newer than the newest one at startup and prints them as
+/// they appear. Runs until the process is interrupted.
+async fn follow_entries(
+    db: &DiaryDB,
+    interval_ms: u64,
+    options: &RenderOptions,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let (newest, _) = db
+        .db
+        .read_entries_after(None, 1, SortOrder::Desc, &EntryFilter::default())
+        .await?;
+    let mut last_id = newest.first().map_or(0, |entry| entry.id);
+
+    info!("Waiting for new entries, press Ctrl-C to stop.");
+    let mut interval = tokio::time::interval(Duration::from_millis(interval_ms.max(1)));
+    loop {
+        interval.tick().await;
+
+        for entry in db.db.read_entries_since(last_id).await? {
+            writeln!(out, "{}\n", export::render_entry(&entry, options)?)?;
+            last_id = last_id.max(entry.id);
+        }
+    }
+}
+
+/// Prints the entries with the given ids in the order they were requested,
+/// noting any ids that don't exist.
+async fn read_entries_by_ids(
+    db: &DiaryDB,
+    ids: Vec<i64>,
+    options: &RenderOptions,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut entries = db.db.read_entries_by_ids(ids.clone()).await?;
+    entries.sort_by_key(|entry| ids.iter().position(|id| *id == entry.id));
+
+    let missing = ids
+        .iter()
+        .filter(|id| !entries.iter().any(|entry| entry.id == **id))
+        .map(|id| id.to_string())
+        .collect::<Vec<String>>();
+    if !missing.is_empty() {
+        info!("Not found: {}", missing.join(", "));
+    }
+
+    output_list(db, out, entries, options).await
+}
+
+/// Explains an empty text listing: either nothing matched the filters or
+/// the diary has no entries at all.
+async fn print_no_entries(db: &DiaryDB, out: &mut dyn Write) -> Result<()> {
+    let total = db.db.count_entries(&EntryFilter::default()).await?;
+
+    if total == 0 {
+        writeln!(out, "\nYour diary is empty.")?;
+    } else {
+        writeln!(
+            out,
+            "\nNo entries match your filters (diary has {} total).",
+            total
+        )?;
+    }
+
+    Ok(())
+}
+
+async fn read_entries_after(
+    db: &DiaryDB,
+    args: Args,
+    per_page: i64,
+    options: &RenderOptions,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let (entries, last_id) = db
+        .db
+        .read_entries_after(
+            args.cursor,
+            per_page,
+            args.sort.unwrap_or(SortOrder::Desc),
+            &entry_filter(&args),
+        )
+        .await?;
+
+    if options.format != OutputFormat::Text {
+        return output_entries(out, entries, options);
+    }
+
+    if entries.is_empty() {
+        if let Some(cursor) = args.cursor {
+            writeln!(out, "\nNo more entries after {}.", cursor)?;
+            return Ok(());
+        }
+        return print_no_entries(db, out).await;
+    }
+
+    print_entries(out, entries, options)?;
+
+    if let Some(id) = last_id {
+        let flag = if args.since_id.is_some() {
+            "--since-id"
+        } else {
+            "--cursor"
+        };
+        writeln!(out, "\nNext page: {} {}", flag, id)?;
+    }
+
+    Ok(())
+}
+
+pub async fn delete_entry(db: &DiaryDB, args: Args, out: &mut dyn Write) -> Result<()> {
+    if args.id.is_none() {
+        return Err(invalid_input(
+            "Entry ID must be provided for this operation.",
+        ));
+    }
+
+    let id = args.id.unwrap();
+
+    if args.dry_run {
+        let entry = require_entry(db, id).await?;
+        writeln!(out, "Dry run: would delete entry {}.", entry.id)?;
+        return Ok(());
+    }
+
+    db.db.delete_entry(id).await?;
+
+    Ok(())
+}
+
+pub async fn restore_entry(db: &DiaryDB, args: Args, out: &mut dyn Write) -> Result<()> {
+    if args.id.is_none() {
+        return Err(invalid_input(
+            "Entry ID must be provided for this operation.",
+        ));
+    }
+
+    if args.dry_run {
+        writeln!(out, "Dry run: would restore entry {}.", args.id.unwrap())?;
+        return Ok(());
+    }
+
+    db.db.restore_entry(args.id.unwrap()).await?;
+
+    Ok(())
+}
+
+/// The `--id` and `--to` entries of link and unlink.
+fn link_ends(args: &Args) -> Result<(i64, i64)> {
+    match (args.id, args.to) {
+        (Some(from), Some(to)) if from == to => {
+            Err(invalid_input("An entry can't be linked to itself."))
+        }
+        (Some(from), Some(to)) => Ok((from, to)),
+        _ => Err(invalid_input(
+            "Both --id and --to must be provided for this operation.",
+        )),
+    }
+}
+
+pub async fn link_entries(db: &DiaryDB, args: Args, out: &mut dyn Write) -> Result<()> {
+    let (from, to) = link_ends(&args)?;
+    require_entry(db, from).await?;
+    require_entry(db, to).await?;
+
+    if args.dry_run {
+        writeln!(out, "Dry run: would link entry {} to {}.", from, to)?;
+        return Ok(());
+    }
+
+    db.db.link_entries(from, to).await?;
+    info!("Linked entry {} to {}.", from, to);
+
+    Ok(())
+}
+
+pub async fn unlink_entries(db: &DiaryDB, args: Args, out: &mut dyn Write) -> Result<()> {
+    let (from, to) = link_ends(&args)?;
+
+    if args.dry_run {
+        writeln!(out, "Dry run: would unlink entry {} from {}.", from, to)?;
+        return Ok(());
+    }
+
+    db.db.unlink_entries(from, to).await?;
+    info!("Unlinked entry {} from {}.", from, to);
+
+    Ok(())
+}
+
+pub async fn purge_entries(db: &DiaryDB, args: Args, out: &mut dyn Write) -> Result<()> {
+    if args.older_than_days.is_none() {
+        return Err(invalid_input(
+            "--older-than-days must be provided for this operation.",
+        ));
+    }
+
+    let days = args.older_than_days.unwrap();
+
+    if args.dry_run {
+        writeln!(
+            out,
+            "Dry run: would purge entries deleted more than {} days ago.",
+            days
+        )?;
+        return Ok(());
+    }
+
+    let purged = db.db.purge_entries(days).await?;
+    info!("Purged {} deleted entries.", purged);
+
+    Ok(())
+}
+
+/// Deletes entries created more than `--older-than-days`, or retention_days
+/// from the config, days ago. Pinned entries are kept unless
+/// `--include-pinned` is given.
+pub async fn prune_entries(
+    db: &DiaryDB,
+    args: Args,
+    config: &Config,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let days = match args.older_than_days.or(config.retention_days) {
+        Some(days) => days,
+        None => {
+            return Err(invalid_input(
+                "--older-than-days or retention_days in the config must be provided for this operation.",
+            ))
+        }
+    };
+
+    if args.dry_run {
+        writeln!(
+            out,
+            "Dry run: would prune {}entries created more than {} days ago.",
+            if args.include_pinned { "" } else { "unpinned " },
+            days
+        )?;
+        return Ok(());
+    }
+
+    let pruned = db.db.prune_entries(days, args.include_pinned).await?;
+    info!("Pruned {} entries older than {} days.", pruned, days);
+
+    Ok(())
+}
+
+/// Whether `--read-only` or read_only in the config forbids writes.
+fn read_only(args: &Args, config: &Config) -> bool {
+    args.read_only || config.read_only.unwrap_or(false)
+}
+
+/// Rejects modes that write to the database while the diary is read-only,
+/// before anything is sent to it.
+fn check_writable(args: &Args, config: &Config) -> Result<()> {
+    if !read_only(args, config) || !args.mode.writes() {
+        return Ok(());
+    }
+
+    let mode = args
+        .mode
+        .to_possible_value()
+        .map_or_else(String::new, |value| value.get_name().to_string());
+    Err(invalid_input(format!(
+        "The {} mode changes the diary and is not available in read-only mode.",
+        mode
+    )))
+}
+
+/// Applies retention_days from the config before commands that write to
+/// the diary anyway. Reads, reports and ping never delete anything; prune
+/// applies the policy on demand.
+async fn apply_retention(db: &DiaryDB, args: &Args, config: &Config) -> Result<()> {
+    let automatic = args.mode.writes()
+        && !matches!(args.mode, Mode::Prune)
+        && !args.dry_run
+        && !read_only(args, config);
+    let days = match config.retention_days {
+        Some(days) if automatic => days,
+        _ => return Ok(()),
+    };
+
+    let pruned = db.db.prune_entries(days, false).await?;
+    if pruned > 0 {
+        info!(
+            "Pruned {} entries older than the {} day retention period.",
+            pruned, days
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn update_entry(
+    db: &DiaryDB,
+    args: Args,
+    config: &Config,
+    out: &mut dyn Write,
+) -> Result<()> {
+    if args.id.is_none() {
+        return Err(invalid_input(
+            "Entry ID must be provided for this operation.",
+        ));
+    }
+
+    let id = args.id.unwrap();
+    let options = render_options(&args, config)?;
+    let mut content = resolve_content(args.content, args.content_file)?;
+
+    if let Some(text) = args.append {
+        if content.is_some() {
+            return Err(invalid_input("--append can't be combined with content."));
+        }
+        return append_content(db, id, text, config, &options, args.dry_run, out).await;
+    }
+
+    if content.is_none()
+        && args.pinned.is_none()
+        && args.starred.is_none()
+        && args.meta.is_none()
+        && args.category.is_none()
+        && io::stdin().is_terminal()
+    {
+        let entry = require_entry(db, id).await?;
+        content = editor::compose(&entry.content)?;
+        if content.is_none() {
+            info!("Entry is unchanged, nothing was updated.");
+            return Ok(());
+        }
+    }
+
+    let content = content.map(normalize_content).transpose()?;
+    if let Some(content) = &content {
+        ContentLimits::from_config(config).check(content)?;
+    }
+
+    if args.dry_run {
+        let entry = require_entry(db, id).await?;
+        writeln!(out, "Dry run: would update entry {}.", id)?;
+        if let Some(content) = &content {
+            writeln!(
+                out,
+                "\nCurrent content:\n{}\n\nProposed content:\n{}",
+                entry.content, content
+            )?;
+        }
+        if let Some(pinned) = args.pinned {
+            writeln!(out, "\nPinned: {} -> {}", entry.pinned, pinned)?;
+        }
+        if let Some(starred) = args.starred {
+            writeln!(out, "\nStarred: {} -> {}", entry.starred, starred)?;
+        }
+        if let Some(metadata) = &args.meta {
+            writeln!(
+                out,
+                "\nMetadata: {} -> {}",
+                entry.metadata.as_ref().unwrap_or(&Value::Null),
+                metadata
+            )?;
+        }
+        if let Some(category) = &args.category {
+            writeln!(
+                out,
+                "\nCategory: {} -> {}",
+                entry.category.as_deref().unwrap_or("(none)"),
+                category
+            )?;
+        }
+        return Ok(());
+    }
+
+    let entry = db
+        .db
+        .update_entry(
+            id,
+            content,
+            args.pinned,
+            args.starred,
+            args.meta,
+            args.expected_version,
+            !args.no_touch,
+            args.category,
+        )
+        .await?;
+
+    print_written_entry(&entry, "Updated", &options, out)
+}
+
+/// Adds a line to the end of an entry. The core appends in a single UPDATE,
+/// so concurrent appends don't overwrite each other.
+async fn append_content(
+    db: &DiaryDB,
+    id: i64,
+    text: String,
+    config: &Config,
+    options: &RenderOptions,
+    dry_run: bool,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let text = normalize_content(text)?;
+    let entry = require_entry(db, id).await?;
+    ContentLimits::from_config(config).check(&format!("{}\n{}", entry.content, text))?;
+
+    if dry_run {
+        writeln!(out, "Dry run: would append to entry {}:\n{}", id, text)?;
+        return Ok(());
+    }
+
+    let entry = db.db.append_content(id, text).await?;
+    print_written_entry(&entry, "Updated", options, out)
+}
+
+/// Sets the pinned flag on every entry, or only on those matching `--substr`.
+async fn set_pinned_bulk(
+    db: &DiaryDB,
+    args: Args,
+    pinned: bool,
+    out: &mut dyn Write,
+) -> Result<()> {
+    if args.dry_run {
+        let action = if pinned { "pin" } else { "unpin" };
+        let count = db
+            .db
+            .count_entries(&EntryFilter {
+                substr: args.substr,
+                ..EntryFilter::default()
+            })
+            .await?;
+        writeln!(out, "Dry run: would {} {} entries.", action, count)?;
+        return Ok(());
+    }
+
+    let changed = db.db.set_pinned_bulk(pinned, args.substr).await?;
+    info!("Set pinned = {} on {} entries.", pinned, changed);
+
+    Ok(())
+}
+
+/// Pins the entry, or sets the state given by `--pinned`. Without `--pinned`
+/// the current state is toggled. With `--all` or `--substr` every matching
+/// entry is pinned instead.
+pub async fn pin_entry(db: &DiaryDB, args: Args, out: &mut dyn Write) -> Result<()> {
+    if args.all || args.substr.is_some() {
+        let pinned = args.pinned.unwrap_or(true);
+        return set_pinned_bulk(db, args, pinned, out).await;
+    }
+
+    if args.id.is_none() {
+        return Err(invalid_input(
+            "Entry ID must be provided for this operation.",
+        ));
+    }
+
+    let id = args.id.unwrap();
+    let pinned = match args.pinned {
+        Some(pinned) => pinned,
+        None => !require_entry(db, id).await?.pinned,
+    };
+
+    if args.dry_run {
+        writeln!(
+            out,
+            "Dry run: would set pinned = {} on entry {}.",
+            pinned, id
+        )?;
+        return Ok(());
+    }
+
+    db.db
+        .update_entry(id, None, Some(pinned), None, None, None, true, None)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn unpin_entry(db: &DiaryDB, args: Args, out: &mut dyn Write) -> Result<()> {
+    if args.all || args.substr.is_some() {
+        return set_pinned_bulk(db, args, false, out).await;
+    }
+
+    if args.id.is_none() {
+        return Err(invalid_input(
+            "Entry ID must be provided for this operation.",
+        ));
+    }
+
+    if args.dry_run {
+        writeln!(out, "Dry run: would unpin entry {}.", args.id.unwrap())?;
+        return Ok(());
+    }
+
+    db.db
+        .update_entry(
+            args.id.unwrap(),
+            None,
+            Some(false),
+            None,
+            None,
+            None,
+            true,
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+
+fn read_export_state(path: &str) -> Result<i64> {
+    match fs::read_to_string(path) {
+        Ok(state) => state
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid export state in {}", path)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path)),
+    }
+}
+
+/// Dumps only the entries newer than `--since-id`, or than the id recorded
+/// by the previous incremental dump, and records the newest exported id.
+async fn dump_new_entries(
+    db: &DiaryDB,
+    args: Args,
+    options: &RenderOptions,
+    config: &Config,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let state_file = args
+        .state_file
+        .or_else(|| config.export_state_file.clone())
+        .unwrap_or_else(|| DEFAULT_EXPORT_STATE_FILE.to_string());
+
+    let since_id = match args.since_id {
+        Some(id) => id,
+        None => read_export_state(&state_file)?,
+    };
+
+    let entries = db.db.read_entries_since(since_id).await?;
+    let rendered = export::render_entries(&entries, options)?;
+    match args.path {
+        Some(p) => fs::write(p, rendered)?,
+        None => writeln!(out, "{}", rendered)?,
+    }
+
+    if let Some(last_id) = entries.iter().map(|entry| entry.id).max() {
+        fs::write(&state_file, last_id.to_string())
+            .with_context(|| format!("Failed to write {}", state_file))?;
+    }
+    info!("Exported {} new entries.", entries.len());
+
+    Ok(())
+}
+
+pub async fn dump_entries(
+    db: &DiaryDB,
+    args: Args,
+    config: &Config,
+    out: &mut dyn Write,
+) -> Result<()> {
+    // Dumps are meant to be saved, so they never contain color codes.
+    let options = RenderOptions {
+        color: false,
+        ..render_options(&args, config)?
+    };
+
+    if args.since_last || args.since_id.is_some() {
+        return dump_new_entries(db, args, &options, config, out).await;
+    }
+
+    // The built-in text dump always uses the local timezone. JSON lines are
+    // streamed by the core dump so large diaries aren't held in memory.
+    let format = match options.format {
+        OutputFormat::Text if options.tz.is_none() => DumpFormat::Text,
+        OutputFormat::JsonLines => DumpFormat::JsonLines,
+        _ => {
+            let rendered = export::render_entries(&fetch_all_entries(db).await?, &options)?;
+            match args.path {
+                Some(p) => fs::write(p, rendered)?,
+                None => writeln!(out, "{}", rendered)?,
+            }
+
+            return Ok(());
+        }
+    };
+
+    match args.path {
+        Some(p) => {
+            db.db.dump_entries(Some(&PathBuf::from(p)), format).await?;
+        }
+        None => {
+            db.db.dump_entries(None, format).await?;
+        }
+    };
+
+    Ok(())
+}
+
+pub async fn import_entries(
+    db: &DiaryDB,
+    args: Args,
+    config: &Config,
+    out: &mut dyn Write,
+) -> Result<()> {
+    if args.path.is_none() {
+        return Err(invalid_input("Path must be provided for this operation."));
+    }
+
+    let path = args.path.unwrap();
+    let text = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path))?;
+
+    let limits = ContentLimits::from_config(config);
+    let rows = import::parse_rows(Path::new(&path), &text)?
+        .into_iter()
+        .map(|(line, row)| {
+            let row = row.and_then(|entry| {
+                check_new_entry(entry, &limits)
+                    .with_context(|| format!("Invalid entry on line {}", line))
+            });
+            (line, row)
+        })
+        .collect::<Vec<_>>();
+
+    if args.continue_on_error {
+        return import_rows(db, rows, args.dry_run, args.error_report, out).await;
+    }
+
+    let entries = rows
+        .into_iter()
+        .map(|(_, row)| row)
+        .collect::<Result<Vec<NewEntry>>>()?;
+
+    if args.dry_run {
+        writeln!(out, "Dry run: would import {} entries.", entries.len())?;
+        return Ok(());
+    }
+
+    let created = db.db.create_entries(entries).await?;
+    info!("Imported {} entries.", created.len());
+
+    Ok(())
+}
+
+/// Imports every valid row on its own, so one bad row doesn't stop the
+/// rest. Failures are logged by the file line their row starts on and
+/// optionally written to a report file.
+async fn import_rows(
+    db: &DiaryDB,
+    rows: Vec<(u64, Result<NewEntry>)>,
+    dry_run: bool,
+    error_report: Option<String>,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut imported = 0;
+    let mut failures = Vec::new();
+
+    for (line, row) in rows {
+        let result = match row {
+            Ok(_) if dry_run => Ok(()),
+            Ok(entry) => db.db.create_entries(vec![entry]).await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(()) => imported += 1,
+            Err(e) => {
+                warn!("Skipped line {}: {:#}", line, e);
+                failures.push(format!("line {}: {:#}", line, e));
+            }
+        }
+    }
+
+    if let Some(report) = error_report {
+        fs::write(&report, failures.join("\n"))
+            .with_context(|| format!("Failed to write {}", report))?;
+    }
+
+    if dry_run {
+        writeln!(
+            out,
+            "Dry run: would import {}, skip {}.",
+            imported,
+            failures.len()
+        )?;
+    } else {
+        info!("Imported {}, skipped {}.", imported, failures.len());
+    }
+
+    Ok(())
+}
+
+pub async fn print_history(db: &DiaryDB, args: Args, out: &mut dyn Write) -> Result<()> {
+    if args.id.is_none() {
+        return Err(invalid_input(
+            "Entry ID must be provided for this operation.",
+        ));
+    }
+
+    let revisions = db.db.read_entry_revisions(args.id.unwrap()).await?;
+
+    if args.format == OutputFormat::Json {
+        writeln!(out, "{}", serde_json::to_string_pretty(&revisions)?)?;
+        return Ok(());
+    }
+
+    if args.format == OutputFormat::JsonLines {
+        for revision in &revisions {
+            writeln!(out, "{}", serde_json::to_string(revision)?)?;
+        }
+        return Ok(());
+    }
+
+    writeln!(out, "\nFound {} revisions.\n", revisions.len())?;
+    let str = revisions
+        .into_iter()
+        .map(|revision| revision.to_string())
+        .collect::<Vec<String>>()
+        .join("\n\n");
+    writeln!(out, "{}", str)?;
+
+    Ok(())
+}
+
+pub async fn print_stats(db: &DiaryDB, args: Args, out: &mut dyn Write) -> Result<()> {
+    let stats = db.db.stats().await?;
+    match args.format {
+        OutputFormat::Text
+        | OutputFormat::Markdown
+        | OutputFormat::Csv
+        | OutputFormat::Porcelain => writeln!(out, "{}", stats)?,
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&stats)?)?,
+        OutputFormat::JsonLines => writeln!(out, "{}", serde_json::to_string(&stats)?)?,
+    }
+
+    Ok(())
+}
+
+/// Prints groups of entries that share the same content, keeping the oldest
+/// entry's text and listing every id in the group.
+pub async fn print_duplicates(
+    db: &DiaryDB,
+    args: Args,
+    config: &Config,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let options = render_options(&args, config)?;
+    let groups = db.db.find_duplicates().await?;
+
+    match options.format {
+        OutputFormat::Json => {
+            writeln!(out, "{}", serde_json::to_string_pretty(&groups)?)?;
+            return Ok(());
+        }
+        OutputFormat::JsonLines => {
+            for group in &groups {
+                writeln!(out, "{}", serde_json::to_string(group)?)?;
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    writeln!(out, "\nFound {} groups of duplicates.\n", groups.len())?;
+    for group in groups {
+        let ids = group
+            .iter()
+            .map(|entry| entry.id.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        writeln!(out, "Entries {}:", ids)?;
+        if let Some(entry) = group.first() {
+            writeln!(out, "{}\n", export::render_entry(entry, &options)?)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies every entry of the `--from-url` database into the configured one,
+/// keeping their creation time, flags, tags, metadata and category.
+pub async fn merge_entries(
+    db: &DiaryDB,
+    args: Args,
+    config: &Config,
+    out: &mut dyn Write,
+) -> Result<()> {
+    if args.from_url.is_none() {
+        return Err(invalid_input(
+            "Source URL must be provided for this operation.",
+        ));
+    }
+
+    let from_url = args.from_url.unwrap();
+    if from_url == config.db_url {
+        return Err(invalid_input(
+            "Source and destination databases are the same.",
+        ));
+    }
+
+    let source = DiaryDB::from_config(&Config {
+        db_url: from_url.clone(),
+        ..config.clone()
+    })
+    .await
+    .with_context(|| format!("Failed to connect to {}", from_url))?;
+
+    let limits = ContentLimits::from_config(config);
+    let result = copy_entries(&source, db, &limits, args.dry_run, args.delete_source, out).await;
+    source.db.close().await;
+
+    result
+}
+
+async fn copy_entries(
+    source: &DiaryDB,
+    destination: &DiaryDB,
+    limits: &ContentLimits,
+    dry_run: bool,
+    delete_source: bool,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let entries = fetch_all_entries(source).await?;
+
+    // Checked before the dry run, so it reports the entries a real merge
+    // would reject.
+    let new_entries = entries
+        .iter()
+        .map(|entry| {
+            let new_entry = NewEntry {
+                content: entry.content.clone(),
+                pinned: entry.pinned,
+                starred: entry.starred,
+                tags: entry.tags.clone(),
+                created_at: Some(entry.created_at),
+                metadata: entry.metadata.clone(),
+                category: entry.category.clone(),
+            };
+            check_new_entry(new_entry, limits)
+                .with_context(|| format!("Entry {} can't be merged", entry.id))
+        })
+        .collect::<Result<Vec<NewEntry>>>()?;
+
+    if dry_run {
+        writeln!(out, "Dry run: would merge {} entries.", entries.len())?;
+        return Ok(());
+    }
+
+    // create_entries inserts everything in a single transaction, so nothing
+    // is deleted from the source unless all of it was copied.
+    let created = destination.db.create_entries(new_entries).await?;
+    info!("Merged {} entries.", created.len());
+
+    if delete_source {
+        // A single hard delete in one transaction: either every merged entry
+        // leaves the source or none does, and no soft-deleted copies stay
+        // behind. The two databases can't share a transaction, so a failure
+        // here leaves the entries in both.
+        let ids = entries.iter().map(|entry| entry.id).collect();
+        let deleted = source.db.hard_delete_entries(ids).await.context(
+            "Entries were merged, but deleting them from the source failed; \
+             the source is unchanged",
+        )?;
+        info!("Deleted {} entries from the source.", deleted);
+    }
+
+    Ok(())
+}
+
+/// Checks that the database answers, without reading or writing entries.
+pub async fn ping(db: &DiaryDB, out: &mut dyn Write) -> Result<()> {
+    let start = Instant::now();
+    db.db.ping().await.context(Unreachable)?;
+    writeln!(out, "OK ({} ms)", start.elapsed().as_millis())?;
+
+    Ok(())
+}
+
+/// Vacuums and analyzes the database, reporting its size before and after
+/// when the backend can measure it.
+pub async fn optimize(db: &DiaryDB, args: Args, out: &mut dyn Write) -> Result<()> {
+    if args.dry_run {
+        writeln!(out, "Dry run: would vacuum and analyze the database.")?;
+        return Ok(());
+    }
+
+    let before = db.db.database_size().await?;
+    db.db.optimize().await?;
+    let after = db.db.database_size().await?;
+
+    match (before, after) {
+        (Some(before), Some(after)) => {
+            info!("Optimized the database: {} -> {} bytes.", before, after)
+        }
+        _ => info!("Optimized the database."),
+    }
+
+    Ok(())
+}
+
+/// Appends to the entry written today, on a new line, or creates one when
+/// there is none yet.
+pub async fn append_entry(
+    db: &DiaryDB,
+    args: Args,
+    config: &Config,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let content = resolve_content(args.content.clone(), args.content_file.clone())?;
+    if content.is_none() {
+        return Err(invalid_input("Content must be provided for this operation"));
+    }
+    let content = normalize_content(content.unwrap())?;
+
+    let today = date::parse("today")?;
+    let entries = db
+        .db
+        .read_entries_between(today.start, today.end, None, None, &EntryFilter::default())
+        .await?;
+
+    let latest = match entries.len() {
+        0 => {
+            let args = Args {
+                content: Some(content),
+                content_file: None,
+                ..args
+            };
+            return create_entry(db, args, config, out).await;
+        }
+        1 => &entries[0],
+        count if args.latest => {
+            info!("{} entries today, appending to the latest.", count);
+            entries.iter().max_by_key(|entry| entry.created_at).unwrap()
+        }
+        count => {
+            return Err(invalid_input(format!(
+                "There are {} entries today, pass --latest to append to the most recent one.",
+                count
+            )))
+        }
+    };
+
+    ContentLimits::from_config(config).check(&format!("{}\n{}", latest.content, content))?;
+
+    if args.dry_run {
+        writeln!(
+            out,
+            "Dry run: would append to entry {}:\n{}",
+            latest.id, content
+        )?;
+        return Ok(());
+    }
+
+    let options = render_options(&args, config)?;
+    let entry = db.db.append_content(latest.id, content).await?;
+
+    print_written_entry(&entry, "Updated", &options, out)
+}
+
+/// Interprets the optional positional argument after the mode: the content
+/// for modes that write an entry, the sort order for everything else. Both
+/// share one slot because clap can't tell them apart by position.
+fn resolve_positional(mut args: Args) -> Result<Args> {
+    let value = match args.positional.take() {
+        Some(value) => value,
+        None => return Ok(args),
+    };
+
+    match args.mode {
+        Mode::Create | Mode::Update | Mode::Append | Mode::Templates => {
+            if args.content.is_some() {
+                return Err(invalid_input(
+                    "Content must be given either as an argument or with --content, not both.",
+                ));
+            }
+            args.content = Some(value);
+        }
+        _ => {
+            let sort = SortOrder::from_str(&value, true).map_err(|_| {
+                invalid_input(format!(
+                    "Invalid sort order '{}', expected asc or desc.",
+                    value
+                ))
+            })?;
+            args.sort = Some(sort);
+        }
+    }
+
+    Ok(args)
+}
+
+/// Lists the saved templates, or saves one when `--template` is given with
+/// its text.
+pub fn manage_templates(args: Args, out: &mut dyn Write) -> Result<()> {
+    let name = match args.template {
+        Some(name) => name,
+        None => {
+            for name in template::list()? {
+                writeln!(out, "{}", name)?;
+            }
+            return Ok(());
+        }
+    };
+
+    let text = resolve_content(args.content, args.content_file)?
+        .ok_or_else(|| invalid_input("Template text must be provided for this operation."))?;
+
+    if args.dry_run {
+        writeln!(out, "Dry run: would save template {}:\n{}", name, text)?;
+        return Ok(());
+    }
+
+    let path = template::save(&name, &text)?;
+    info!("Saved template {} to {}.", name, path.display());
+
+    Ok(())
+}
+
+/// Prints every category in use with its number of entries.
+pub async fn print_categories(db: &DiaryDB, args: Args, out: &mut dyn Write) -> Result<()> {
+    let categories = db.db.list_categories().await?;
+
+    if matches!(args.format, OutputFormat::Json | OutputFormat::JsonLines) {
+        let categories = categories
+            .into_iter()
+            .map(|(name, count)| serde_json::json!({ "category": name, "count": count }))
+            .collect::<Vec<Value>>();
+        if args.format == OutputFormat::Json {
+            writeln!(out, "{}", serde_json::to_string_pretty(&categories)?)?;
+        } else {
+            for category in &categories {
+                writeln!(out, "{}", serde_json::to_string(category)?)?;
+            }
+        }
+        return Ok(());
+    }
+
+    for (name, count) in categories {
+        writeln!(out, "{}\t{}", name, count)?;
+    }
+
+    Ok(())
+}
+
+/// The system's IANA timezone, for queries that bucket by calendar day in
+/// the database and so can't use chrono's `Local`.
+fn local_timezone() -> Result<Tz> {
+    let name = iana_time_zone::get_timezone()
+        .context("Could not determine the local timezone, set --tz or display_timezone")?;
+    name.parse::<Tz>()
+        .map_err(|e| invalid_input(format!("Unknown local timezone '{}': {}", name, e)))
+}
+
+/// Prints how many entries were written per day, week or month as an ASCII
+/// bar chart. Buckets follow the display timezone, or the local one when
+/// none is set, like every other timestamp the CLI shows.
+pub async fn print_summary(
+    db: &DiaryDB,
+    args: Args,
+    config: &Config,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let options = render_options(&args, config)?;
+    let tz = match options.tz {
+        Some(tz) => tz,
+        None => local_timezone()?,
+    };
+    let buckets = db.db.entry_counts_by_period(args.period, Some(tz)).await?;
+
+    if matches!(options.format, OutputFormat::Json | OutputFormat::JsonLines) {
+        let buckets = buckets
+            .into_iter()
+            .map(|(period, count)| serde_json::json!({ "period": period, "count": count }))
+            .collect::<Vec<Value>>();
+        if options.format == OutputFormat::Json {
+            writeln!(out, "{}", serde_json::to_string_pretty(&buckets)?)?;
+        } else {
+            for bucket in &buckets {
+                writeln!(out, "{}", serde_json::to_string(bucket)?)?;
+            }
+        }
+        return Ok(());
+    }
+
+    let max = buckets.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    for (period, count) in buckets {
+        let width = if max > 0 {
+            (count * SUMMARY_BAR_WIDTH + max - 1) / max
+        } else {
+            0
+        };
+        writeln!(
+            out,
+            "{}  {:>5}  {}",
+            period,
+            count,
+            "#".repeat(width as usize)
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Prints the entries written on the same calendar day in every year,
+/// grouped by year, oldest first.
+pub async fn print_on_this_day(
+    db: &DiaryDB,
+    args: Args,
+    config: &Config,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let options = render_options(&args, config)?;
+    let days = match args.day {
+        Some(day) => vec![day],
+        None => date::on_this_day(Local::now().date_naive()),
+    };
+
+    let mut entries = Vec::new();
+    for (month, day) in days {
+        entries.extend(db.db.read_on_this_day(month, day).await?);
+    }
+    entries.sort_by_key(|entry| entry.created_at);
+
+    if options.format != OutputFormat::Text || !options.fields.is_empty() {
+        return output_entries(out, entries, &options);
+    }
+
+    if entries.is_empty() {
+        info!("No entries were written on this day.");
+        return Ok(());
+    }
+
+    let year = |entry: &Entry| match options.tz {
+        Some(tz) => entry.created_at.with_timezone(&tz).year(),
+        None => entry.created_at.with_timezone(&Local).year(),
+    };
+
+    let mut current = None;
+    for entry in &entries {
+        if current != Some(year(entry)) {
+            current = Some(year(entry));
+            writeln!(out, "\n== {} ==\n", year(entry))?;
+        }
+        writeln!(out, "{}\n", export::render_entry(entry, &options)?)?;
+    }
+
+    Ok(())
+}
+
+/// Writes a commented starter config file to `path`, using a SQLite
+/// database in the platform data directory. An existing file is only
+/// replaced with `--force`.
+pub fn init_config(path: &str, force: bool, dry_run: bool, out: &mut dyn Write) -> Result<()> {
+    let db_path = default_db_path()?;
+    let template = config_template(&db_path);
+
+    if dry_run {
+        if Path::new(path).exists() && !force {
+            return Err(invalid_input(format!(
+                "{} already exists, pass --force to overwrite it.",
+                path
+            )));
+        }
+        writeln!(out, "Dry run: would write {}:\n\n{}", path, template)?;
+        return Ok(());
+    }
+
+    if let Some(dir) = db_path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut file = open_output(path, force)?;
+    file.write_all(template.as_bytes())
+        .with_context(|| format!("Failed to write {}", path))?;
+    info!("Wrote {}", path);
+
+    Ok(())
+}
+
+/// diary/diary.db in the platform data directory ($XDG_DATA_HOME or
+/// ~/.local/share on Linux), or in the working directory when there is no
+/// home directory. Always absolute, so the database doesn't depend on where
+/// diary runs.
+fn default_db_path() -> Result<PathBuf> {
+    match dirs::data_dir() {
+        Some(dir) => Ok(dir.join("diary").join(DB_FILE)),
+        None => Ok(env::current_dir()
+            .context("Failed to determine the working directory")?
+            .join(DB_FILE)),
+    }
+}
+
+fn config_template(db_path: &Path) -> String {
+    CONFIG_SAMPLE.replacen(SAMPLE_DB_URL, &format!("sqlite://{}", db_path.display()), 1)
+}
+
+pub async fn process_args(
+    db: &Arc<DiaryDB>,
+    args: Args,
+    config: &Config,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let args = resolve_positional(args)?;
+    check_writable(&args, config)?;
+    apply_retention(db, &args, config).await?;
+
+    match args.mode {
+        Mode::Create => create_entry(db, args, config, out).await?,
+        Mode::Read => read_entry(db, args, config, out).await?,
+        Mode::Delete => delete_entry(db, args, out).await?,
+        Mode::Update => update_entry(db, args, config, out).await?,
+        Mode::DumpAll => dump_entries(db, args, config, out).await?,
+        Mode::Restore => restore_entry(db, args, out).await?,
+        Mode::Purge => purge_entries(db, args, out).await?,
+        Mode::Stats => print_stats(db, args, out).await?,
+        Mode::Import => import_entries(db, args, config, out).await?,
+        Mode::Pin => pin_entry(db, args, out).await?,
+        Mode::Unpin => unpin_entry(db, args, out).await?,
+        Mode::History => print_history(db, args, out).await?,
+        Mode::Serve => {
+            server::serve(
+                Arc::clone(db),
+                PageLimit::from_config(config),
+                ContentLimits::from_config(config),
+                &args.bind,
+            )
+            .await?
+        }
+        Mode::Duplicates => print_duplicates(db, args, config, out).await?,
+        Mode::Merge => merge_entries(db, args, config, out).await?,
+        Mode::Ping => ping(db, out).await?,
+        Mode::Optimize => optimize(db, args, out).await?,
+        Mode::Append => append_entry(db, args, config, out).await?,
+        Mode::Templates => manage_templates(args, out)?,
+        Mode::Categories => print_categories(db, args, out).await?,
+        Mode::Summary => print_summary(db, args, config, out).await?,
+        Mode::Prune => prune_entries(db, args, config, out).await?,
+        Mode::OnThisDay => print_on_this_day(db, args, config, out).await?,
+        // Runs in main, before the config is loaded and the database opened.
+        Mode::Init => unreachable!("init is handled before connecting"),
+        Mode::Link => link_entries(db, args, out).await?,
+        Mode::Unlink => unlink_entries(db, args, out).await?,
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::error::{exit_code, EXIT_INVALID_INPUT};
+    use super::*;
+
+    fn assert_invalid_input(result: Result<impl std::fmt::Debug>) {
+        assert_eq!(exit_code(&result.unwrap_err()), EXIT_INVALID_INPUT);
+    }
+
+    #[test]
+    fn normalize_content_trims_surrounding_whitespace() {
+        let content = normalize_content("\n  dear diary \t\n".to_string()).unwrap();
+        assert_eq!(content, "dear diary");
+    }
+
+    #[test]
+    fn normalize_content_rejects_empty_and_whitespace_only_content() {
+        assert_invalid_input(normalize_content(String::new()));
+        assert_invalid_input(normalize_content(" \t\r\n ".to_string()));
+        assert_invalid_input(normalize_content("\u{3000}\u{2003}".to_string()));
+    }
+
+    fn new_entry(content: &str) -> NewEntry {
+        NewEntry {
+            content: content.to_string(),
+            pinned: false,
+            starred: false,
+            tags: Vec::new(),
+            created_at: None,
+            metadata: None,
+            category: None,
+        }
+    }
+
+    #[test]
+    fn imported_and_merged_entries_get_the_create_checks() {
+        let limits = ContentLimits {
+            max_bytes: 8,
+            max_lines: 2,
+        };
+
+        let entry = check_new_entry(new_entry("  trimmed \n"), &limits).unwrap();
+        assert_eq!(entry.content, "trimmed");
+
+        assert_invalid_input(check_new_entry(new_entry(" \n\t"), &limits));
+        assert_invalid_input(check_new_entry(new_entry("too long!"), &limits));
+        assert_invalid_input(check_new_entry(new_entry("a\nb\nc"), &limits));
+    }
+
+    fn page_limit(clamp: bool) -> PageLimit {
+        PageLimit {
+            default: None,
+            max: 100,
+            clamp,
+        }
+    }
+
+    #[test]
+    fn page_limit_accepts_the_maximum_in_both_modes() {
+        assert_eq!(page_limit(true).resolve(Some(100)).unwrap(), 100);
+        assert_eq!(page_limit(false).resolve(Some(100)).unwrap(), 100);
+    }
+
+    #[test]
+    fn page_limit_clamps_or_rejects_one_above_the_maximum() {
+        assert_eq!(page_limit(true).resolve(Some(101)).unwrap(), 100);
+        assert_invalid_input(page_limit(false).resolve(Some(101)));
+    }
+
+    #[test]
+    fn page_limit_rejects_zero_and_negative_sizes_in_both_modes() {
+        for clamp in [true, false] {
+            assert_invalid_input(page_limit(clamp).resolve(Some(0)));
+            assert_invalid_input(page_limit(clamp).resolve(Some(-1)));
+        }
+    }
+
+    #[test]
+    fn updated_window_is_part_of_the_row_filter() {
+        let args = Args::try_parse_from([
+            "diary",
+            "r",
+            "--updated-after",
+            "2024-05-01T00:00:00Z",
+            "--updated-before",
+            "2024-06-01T00:00:00+02:00",
+            "--page",
+            "2",
+        ])
+        .unwrap();
+        let filter = entry_filter(&args);
+
+        assert_eq!(
+            filter.updated_after,
+            Some(date::parse_timestamp("2024-05-01T00:00:00Z").unwrap())
+        );
+        assert_eq!(
+            filter.updated_before,
+            Some(date::parse_timestamp("2024-05-31T22:00:00Z").unwrap())
+        );
+    }
+
+    #[test]
+    fn init_template_is_the_sample_with_an_absolute_database_url() {
+        let db_path = Path::new("/data/diary/diary.db");
+        let template = config_template(db_path);
+
+        assert!(template.contains("\nurl = sqlite:///data/diary/diary.db\n"));
+        assert!(!template.contains(SAMPLE_DB_URL));
+        assert_eq!(
+            template.replace("/data/diary/", "/home/me/.local/share/diary/"),
+            CONFIG_SAMPLE
+        );
+    }
+
+    #[test]
+    fn page_limit_falls_back_to_the_default_page_size() {
+        assert_eq!(page_limit(false).resolve(None).unwrap(), DEFAULT_PER_PAGE);
+    }
+}
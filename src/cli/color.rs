@@ -0,0 +1,48 @@
+use std::io::{self, IsTerminal};
+
+use clap::ValueEnum;
+use owo_colors::OwoColorize;
+use regex::Regex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Whether to colorize output. `auto` colors only when stdout is a terminal
+/// and `NO_COLOR` is unset or empty.
+pub fn enabled(choice: ColorChoice) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            std::env::var_os("NO_COLOR").map_or(true, |value| value.is_empty())
+                && io::stdout().is_terminal()
+        }
+    }
+}
+
+/// Wraps every match of `pattern` in `text` in a highlight.
+pub fn highlight(text: &str, pattern: &Regex) -> String {
+    let mut highlighted = String::with_capacity(text.len());
+    let mut last = 0;
+
+    for found in pattern.find_iter(text) {
+        highlighted.push_str(&text[last..found.start()]);
+        highlighted.push_str(&found.as_str().black().on_yellow().to_string());
+        last = found.end();
+    }
+    highlighted.push_str(&text[last..]);
+
+    highlighted
+}
+
+/// Colors the first line of a rendered entry, which holds its header.
+pub fn pinned_header(text: &str) -> String {
+    match text.split_once('\n') {
+        Some((header, rest)) => format!("{}\n{}", header.yellow().bold(), rest),
+        None => text.yellow().bold().to_string(),
+    }
+}
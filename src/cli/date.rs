@@ -0,0 +1,171 @@
+use anyhow::{Context, Error, Result};
+use chrono::{
+    DateTime, Datelike, Days, Duration, Local, Months, NaiveDate, NaiveTime, TimeZone, Utc,
+};
+
+/// Half-open `[start, end)` interval of time, in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateRange {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// Parses a date expression into a range of whole local days:
+///
+/// - `today`, `yesterday` or an absolute `YYYY-MM-DD`: that single day;
+/// - `Nd`, `Nw`, `Nm`: from N days, weeks or months ago up to the end of today;
+/// - `thismonth`: from the first of the current month up to the end of today.
+pub fn parse(expr: &str) -> Result<DateRange> {
+    parse_at(expr, Local::now().date_naive())
+}
+
+/// Parses an RFC 3339 timestamp, e.g. `2019-05-04T21:30:00+02:00`.
+pub fn parse_timestamp(value: &str) -> Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|time| time.with_timezone(&Utc))
+        .with_context(|| format!("Invalid timestamp '{}', expected RFC 3339", value))
+}
+
+/// Parses one end of a time window: an RFC 3339 timestamp, or a date
+/// expression accepted by `parse`, which stands for the start of its range.
+pub fn parse_bound(value: &str) -> Result<DateTime<Utc>> {
+    match parse_timestamp(value) {
+        Ok(time) => Ok(time),
+        Err(_) => Ok(parse(value)?.start),
+    }
+}
+
+/// Parses a calendar day without a year, `MM-DD`. `02-29` is accepted.
+pub fn parse_month_day(value: &str) -> Result<(u32, u32)> {
+    // 2000 is a leap year, so every month-day pair that exists in some year
+    // parses.
+    let date = NaiveDate::parse_from_str(&format!("2000-{}", value.trim()), "%Y-%m-%d")
+        .with_context(|| format!("Invalid day '{}', expected MM-DD", value))?;
+
+    Ok((date.month(), date.day()))
+}
+
+/// The calendar days recalled "on this day" for `today`. On February 28 of
+/// a non-leap year, February 29 is included so leap-day entries still come
+/// up.
+pub fn on_this_day(today: NaiveDate) -> Vec<(u32, u32)> {
+    let mut days = vec![(today.month(), today.day())];
+    if (today.month(), today.day()) == (2, 28) && !today.leap_year() {
+        days.push((2, 29));
+    }
+
+    days
+}
+
+fn parse_at(expr: &str, today: NaiveDate) -> Result<DateRange> {
+    let expr = expr.trim().to_lowercase();
+    let tomorrow = next_day(today)?;
+
+    let (first, end) = match expr.as_str() {
+        "today" => (today, tomorrow),
+        "yesterday" => (days_ago(today, 1)?, today),
+        "thismonth" => (today.with_day(1).context("Invalid date")?, tomorrow),
+        _ => {
+            if let Ok(date) = NaiveDate::parse_from_str(&expr, "%Y-%m-%d") {
+                (date, next_day(date)?)
+            } else {
+                (relative_start(&expr, today)?, tomorrow)
+            }
+        }
+    };
+
+    Ok(DateRange {
+        start: start_of_day(first)?,
+        end: start_of_day(end)?,
+    })
+}
+
+fn relative_start(expr: &str, today: NaiveDate) -> Result<NaiveDate> {
+    let invalid = || {
+        Error::msg(format!(
+            "Invalid date '{}', expected today, yesterday, thismonth, Nd, Nw, Nm or YYYY-MM-DD",
+            expr
+        ))
+    };
+
+    let (split, _) = expr.char_indices().last().ok_or_else(invalid)?;
+    let (count, unit) = expr.split_at(split);
+    let count: u32 = count.parse().map_err(|_| invalid())?;
+
+    match unit {
+        "d" => days_ago(today, count.into()),
+        "w" => days_ago(today, u64::from(count) * 7),
+        // Clamps to the last day of shorter months, so 1m from March 31 is
+        // the end of February.
+        "m" => today
+            .checked_sub_months(Months::new(count))
+            .context("Date is out of range"),
+        _ => Err(invalid()),
+    }
+}
+
+fn days_ago(date: NaiveDate, days: u64) -> Result<NaiveDate> {
+    date.checked_sub_days(Days::new(days))
+        .context("Date is out of range")
+}
+
+fn next_day(date: NaiveDate) -> Result<NaiveDate> {
+    date.succ_opt().context("Date is out of range")
+}
+
+/// Local midnight of `date` in UTC. On days where a DST change skips
+/// midnight, the first existing local time after it is used instead.
+fn start_of_day(date: NaiveDate) -> Result<DateTime<Utc>> {
+    start_of_day_in(date, &Local)
+}
+
+fn start_of_day_in<Z: TimeZone>(date: NaiveDate, zone: &Z) -> Result<DateTime<Utc>> {
+    let midnight = date.and_time(NaiveTime::MIN);
+
+    zone.from_local_datetime(&midnight)
+        .earliest()
+        .or_else(|| {
+            zone.from_local_datetime(&(midnight + Duration::hours(1)))
+                .earliest()
+        })
+        .map(|start| start.with_timezone(&Utc))
+        .with_context(|| format!("{} has no local midnight", date))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        parse_timestamp(value).unwrap()
+    }
+
+    fn day(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn one_month_from_march_31_starts_at_the_end_of_february() {
+        let range = parse_at("1m", day("2023-03-31")).unwrap();
+        assert_eq!(range.start, start_of_day(day("2023-02-28")).unwrap());
+        assert_eq!(range.end, start_of_day(day("2023-04-01")).unwrap());
+
+        let range = parse_at("1m", day("2024-03-31")).unwrap();
+        assert_eq!(range.start, start_of_day(day("2024-02-29")).unwrap());
+    }
+
+    #[test]
+    fn day_without_a_local_midnight_starts_at_the_first_hour_after_it() {
+        // Sao Paulo skipped from 00:00 to 01:00 (-03:00 to -02:00) that day.
+        let zone = chrono_tz::America::Sao_Paulo;
+
+        assert_eq!(
+            start_of_day_in(day("2018-11-04"), &zone).unwrap(),
+            utc("2018-11-04T03:00:00Z")
+        );
+        assert_eq!(
+            start_of_day_in(day("2018-11-05"), &zone).unwrap(),
+            utc("2018-11-05T02:00:00Z")
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use std::{env, fs, io::Write, process::Command};
+
+use anyhow::{Context, Error, Result};
+use tempfile::Builder;
+
+#[cfg(windows)]
+const DEFAULT_EDITOR: &str = "notepad";
+#[cfg(not(windows))]
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Opens `$EDITOR` on a temporary file pre-filled with `initial` and returns
+/// the saved text, or `None` if it was left empty or unchanged.
+pub fn compose(initial: &str) -> Result<Option<String>> {
+    let editor = env::var("EDITOR").unwrap_or_default();
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or(DEFAULT_EDITOR);
+
+    // Created with a random name, O_EXCL and owner-only permissions, so
+    // other users can neither read the entry nor plant a symlink in its
+    // place. The file is removed when `file` is dropped.
+    let mut file = Builder::new()
+        .prefix("diary-")
+        .suffix(".txt")
+        .tempfile()
+        .context("Failed to create temporary file for the editor")?;
+    file.write_all(initial.as_bytes())
+        .and_then(|_| file.flush())
+        .context("Failed to write temporary file for the editor")?;
+    let path = file.path();
+
+    let content = Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to launch editor {}", program))
+        .and_then(|status| {
+            if !status.success() {
+                return Err(Error::msg(format!(
+                    "Editor {} exited with {}",
+                    program, status
+                )));
+            }
+            fs::read_to_string(path).context("Failed to read the edited file")
+        });
+    let content = content?;
+
+    if content.trim().is_empty() || content == initial {
+        return Ok(None);
+    }
+
+    Ok(Some(content))
+}
@@ -0,0 +1,160 @@
+use std::fmt;
+
+use anyhow::Error;
+use clap::ValueEnum;
+use diary_core::DiaryError;
+
+/// Exit status for failures that don't fit a more specific category.
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit status when the requested entry does not exist.
+pub const EXIT_NOT_FOUND: i32 = 2;
+/// Exit status for missing or invalid command line arguments.
+pub const EXIT_INVALID_INPUT: i32 = 3;
+/// Exit status when the config file exists but could not be loaded.
+pub const EXIT_CONFIG: i32 = 4;
+/// Exit status when the database connection could not be established or
+/// the database stopped responding.
+pub const EXIT_CONNECTION: i32 = 5;
+/// Exit status when an update lost a race with another writer.
+pub const EXIT_CONFLICT: i32 = 6;
+/// Exit status when the operation was interrupted with Ctrl-C.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// The exit statuses above, listed at the end of `--help`.
+pub const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0    Success
+  1    Any other failure
+  2    The entry does not exist
+  3    Missing or invalid command line arguments
+  4    The config file is missing or could not be loaded
+  5    The database could not be reached
+  6    The entry was changed by another writer (--expected-version)
+  130  Interrupted with Ctrl-C";
+
+/// How a failed operation is reported on stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+    Text,
+    /// A single `{"error": kind, "message": ...}` object.
+    Json,
+}
+
+/// Missing or invalid command line arguments.
+#[derive(Debug)]
+pub struct InvalidInput(pub String);
+
+impl fmt::Display for InvalidInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidInput {}
+
+/// The config file is missing or could not be loaded.
+#[derive(Debug)]
+pub struct InvalidConfig(pub String);
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidConfig {}
+
+/// The database could not be reached, either on connect or on a health
+/// check.
+#[derive(Debug)]
+pub struct Unreachable;
+
+impl fmt::Display for Unreachable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Database is unreachable")
+    }
+}
+
+pub fn invalid_input(msg: impl Into<String>) -> Error {
+    InvalidInput(msg.into()).into()
+}
+
+pub fn invalid_config(msg: impl Into<String>) -> Error {
+    InvalidConfig(msg.into()).into()
+}
+
+/// Maps an error returned by a CLI operation to the process exit status.
+pub fn exit_code(e: &Error) -> i32 {
+    if e.downcast_ref::<InvalidInput>().is_some() {
+        return EXIT_INVALID_INPUT;
+    }
+
+    if e.downcast_ref::<InvalidConfig>().is_some() {
+        return EXIT_CONFIG;
+    }
+
+    if e.downcast_ref::<Unreachable>().is_some() {
+        return EXIT_CONNECTION;
+    }
+
+    match e.downcast_ref::<DiaryError>() {
+        Some(DiaryError::NotFound { .. }) => EXIT_NOT_FOUND,
+        Some(DiaryError::Conflict { .. }) => EXIT_CONFLICT,
+        _ => EXIT_FAILURE,
+    }
+}
+
+/// Prints an error returned by a CLI operation to stderr.
+pub fn print_error(e: &Error, format: ErrorFormat) {
+    match format {
+        ErrorFormat::Text => eprintln!("Error: {:?}", e),
+        ErrorFormat::Json => eprintln!("{}", error_json(e)),
+    }
+}
+
+/// The JSON form of an error. `error` is a stable kind: InvalidInput,
+/// InvalidConfig, Unreachable, the `DiaryError` variant name, or Error for
+/// anything else.
+fn error_json(e: &Error) -> serde_json::Value {
+    let mut json = serde_json::json!({ "error": "Error", "message": format!("{:#}", e) });
+
+    if e.downcast_ref::<InvalidInput>().is_some() {
+        json["error"] = "InvalidInput".into();
+    } else if e.downcast_ref::<InvalidConfig>().is_some() {
+        json["error"] = "InvalidConfig".into();
+    } else if e.downcast_ref::<Unreachable>().is_some() {
+        json["error"] = "Unreachable".into();
+    } else if let Some(diary_error) = e.downcast_ref::<DiaryError>() {
+        json["error"] = diary_error.kind().into();
+        if let DiaryError::NotFound { id } = diary_error {
+            json["id"] = (*id).into();
+        }
+    }
+
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn help_lists_every_exit_code() {
+        for code in [
+            EXIT_FAILURE,
+            EXIT_NOT_FOUND,
+            EXIT_INVALID_INPUT,
+            EXIT_CONFIG,
+            EXIT_CONNECTION,
+            EXIT_CONFLICT,
+            EXIT_INTERRUPTED,
+        ] {
+            let line = format!("\n  {:<4} ", code);
+            assert!(
+                EXIT_CODES_HELP.contains(&line),
+                "exit code {} is not listed",
+                code
+            );
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use diary_core::models::Entry;
+use regex::Regex;
+use serde_json::Value;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::color;
+
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+const COMPACT_CONTENT_WIDTH: usize = 60;
+const CSV_HEADER: [&str; 9] = [
+    "id",
+    "created_at",
+    "updated_at",
+    "pinned",
+    "starred",
+    "tags",
+    "category",
+    "metadata",
+    "content",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    /// One compact JSON object per line.
+    JsonLines,
+    Markdown,
+    Csv,
+    /// Stable tab-separated `id, created_at, pinned, content, tags` lines for
+    /// scripts, with newlines, tabs and backslashes in content escaped and
+    /// tags separated by commas.
+    Porcelain,
+}
+
+/// Entry fields that `--fields` can select.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Field {
+    Id,
+    #[value(name = "created_at")]
+    CreatedAt,
+    #[value(name = "updated_at")]
+    UpdatedAt,
+    Pinned,
+    Starred,
+    Content,
+    Category,
+    Tags,
+}
+
+impl Field {
+    /// The field's key in an entry's JSON form.
+    fn key(self) -> &'static str {
+        match self {
+            Field::Id => "id",
+            Field::CreatedAt => "created_at",
+            Field::UpdatedAt => "updated_at",
+            Field::Pinned => "pinned",
+            Field::Starred => "starred",
+            Field::Content => "content",
+            Field::Category => "category",
+            Field::Tags => "tags",
+        }
+    }
+}
+
+/// How entries are rendered for output.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub format: OutputFormat,
+    /// Timezone for displayed timestamps, `None` for the local timezone.
+    pub tz: Option<Tz>,
+    /// Colorize text output.
+    pub color: bool,
+    /// Matches highlighted in colored text output.
+    pub highlight: Option<Regex>,
+    /// Lines kept around each `highlight` match in text output, the whole
+    /// content when `None`.
+    pub context: Option<usize>,
+    /// Render text output one line per entry.
+    pub compact: bool,
+    /// Grapheme clusters of content shown per entry in text listings, all
+    /// of it when `None`.
+    pub preview_chars: Option<usize>,
+    /// Only output these fields, all of them when empty.
+    pub fields: Vec<Field>,
+}
+
+/// Renders a single entry.
+pub fn render_entry(entry: &Entry, options: &RenderOptions) -> Result<String> {
+    if !options.fields.is_empty() {
+        return projected_entries(std::slice::from_ref(entry), options);
+    }
+
+    let rendered = match options.format {
+        OutputFormat::Text => {
+            let text = match options.tz {
+                Some(tz) => entry.render(tz),
+                None => entry.to_string(),
+            };
+            if options.color {
+                colorize(entry, text, options.highlight.as_ref())
+            } else {
+                text
+            }
+        }
+        OutputFormat::Json => serde_json::to_string_pretty(entry)?,
+        OutputFormat::JsonLines => serde_json::to_string(entry)?,
+        OutputFormat::Markdown => markdown_entry(entry, options.tz),
+        OutputFormat::Csv => csv_entries(std::slice::from_ref(entry))?,
+        OutputFormat::Porcelain => porcelain_entry(entry),
+    };
+
+    Ok(rendered)
+}
+
+/// Renders a list of entries. JSON produces a single array and CSV a single
+/// table, JSON lines and porcelain put one entry per line and the other
+/// formats separate entries with a blank line.
+pub fn render_entries(entries: &[Entry], options: &RenderOptions) -> Result<String> {
+    if !options.fields.is_empty() {
+        return projected_entries(entries, options);
+    }
+
+    match options.format {
+        OutputFormat::Json => return Ok(serde_json::to_string_pretty(entries)?),
+        OutputFormat::Csv => return csv_entries(entries),
+        _ => {}
+    }
+
+    let separator = match options.format {
+        OutputFormat::JsonLines | OutputFormat::Porcelain => "\n",
+        _ => "\n\n",
+    };
+
+    Ok(entries
+        .iter()
+        .map(|entry| render_entry(entry, options))
+        .collect::<Result<Vec<String>>>()?
+        .join(separator))
+}
+
+/// Renders an entry on a single line: id, creation time, a pin marker and
+/// the start of its content with line breaks folded into spaces.
+pub fn compact_entry(entry: &Entry, options: &RenderOptions) -> String {
+    let content = entry
+        .content
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ");
+    let (mut preview, truncated) = truncate(&content, COMPACT_CONTENT_WIDTH);
+    if truncated {
+        preview.push('…');
+    }
+
+    let mut line = format!(
+        "#{} [{}]",
+        entry.id,
+        format_time(entry.created_at, options.tz)
+    );
+    if entry.pinned {
+        line.push_str(" 📌");
+    }
+    if !entry.tags.is_empty() {
+        line.push_str(&format!(" ({})", entry.tags.join(", ")));
+    }
+    line.push(' ');
+    line.push_str(&preview);
+
+    if options.color {
+        colorize(entry, line, options.highlight.as_ref())
+    } else {
+        line
+    }
+}
+
+/// The first `max` grapheme clusters of `text`, so multibyte characters and
+/// combining sequences are never split, and whether anything was cut off.
+pub fn truncate(text: &str, max: usize) -> (String, bool) {
+    match text.grapheme_indices(true).nth(max) {
+        Some((end, _)) => (text[..end].to_string(), true),
+        None => (text.to_string(), false),
+    }
+}
+
+fn colorize(entry: &Entry, text: String, highlight: Option<&Regex>) -> String {
+    let text = match highlight {
+        Some(pattern) => color::highlight(&text, pattern),
+        None => text,
+    };
+
+    if entry.pinned {
+        color::pinned_header(&text)
+    } else {
+        text
+    }
+}
+
+/// Keeps only the lines of `content` matching `pattern` and `context` lines
+/// around each, grep style, with an ellipsis line between separate regions.
+/// Content without a matching line is returned unchanged.
+pub fn excerpt(content: &str, pattern: &Regex, context: usize) -> String {
+    let lines = content.lines().collect::<Vec<&str>>();
+
+    let mut regions: Vec<(usize, usize)> = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if !pattern.is_match(line) {
+            continue;
+        }
+
+        let start = i.saturating_sub(context);
+        let end = i.saturating_add(context).min(lines.len() - 1);
+        match regions.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = end,
+            _ => regions.push((start, end)),
+        }
+    }
+
+    if regions.is_empty() {
+        return content.to_string();
+    }
+
+    regions
+        .iter()
+        .map(|&(start, end)| lines[start..=end].join("\n"))
+        .collect::<Vec<String>>()
+        .join("\n…\n")
+}
+
+/// Formats a timestamp in `tz`, or the local timezone. UTC times get a `Z`
+/// suffix so they can't be mistaken for local ones.
+fn format_time(time: DateTime<Utc>, tz: Option<Tz>) -> String {
+    match tz {
+        Some(Tz::UTC) => format!("{}Z", time.format(DATE_FORMAT)),
+        Some(tz) => time.with_timezone(&tz).format(DATE_FORMAT).to_string(),
+        None => time.with_timezone(&Local).format(DATE_FORMAT).to_string(),
+    }
+}
+
+fn markdown_entry(entry: &Entry, tz: Option<Tz>) -> String {
+    let mut markdown = format!("## {}", format_time(entry.created_at, tz));
+    if entry.pinned {
+        markdown.push_str(" 📌");
+    }
+    if entry.starred {
+        markdown.push_str(" ⭐");
+    }
+
+    markdown.push_str("\n\n");
+    markdown.push_str(entry.content.trim_end());
+
+    if !entry.tags.is_empty() {
+        markdown.push_str(&format!("\n\nTags: {}", entry.tags.join(", ")));
+    }
+    if let Some(updated_at) = entry.updated_at {
+        markdown.push_str(&format!("\n\n*(updated {})*", format_time(updated_at, tz)));
+    }
+
+    markdown
+}
+
+/// Renders only the selected fields: JSON objects for the JSON formats, a
+/// table for CSV and escaped tab-separated lines, in the requested field
+/// order, otherwise. The entries are read in full; the core reads have no
+/// column projection yet.
+fn projected_entries(entries: &[Entry], options: &RenderOptions) -> Result<String> {
+    let rows = entries
+        .iter()
+        .map(|entry| entry_json(entry, &options.fields))
+        .collect::<Result<Vec<_>>>()?;
+
+    let text = |value: &Value| match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    };
+
+    match options.format {
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(&rows)?),
+        OutputFormat::JsonLines => Ok(rows
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<serde_json::Result<Vec<String>>>()?
+            .join("\n")),
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer.write_record(options.fields.iter().map(|field| field.key()))?;
+            for row in &rows {
+                writer.write_record(options.fields.iter().map(|field| text(&row[field.key()])))?;
+            }
+            Ok(String::from_utf8(writer.into_inner()?)?)
+        }
+        _ => Ok(rows
+            .iter()
+            .map(|row| {
+                options
+                    .fields
+                    .iter()
+                    .map(|field| escape(&text(&row[field.key()])))
+                    .collect::<Vec<String>>()
+                    .join("\t")
+            })
+            .collect::<Vec<String>>()
+            .join("\n")),
+    }
+}
+
+/// An entry's JSON object with only the `fields` keys, or with every key
+/// when `fields` is empty.
+pub fn entry_json(entry: &Entry, fields: &[Field]) -> Result<Value> {
+    let json = serde_json::to_value(entry)?;
+    if fields.is_empty() {
+        return Ok(json);
+    }
+
+    Ok(Value::Object(
+        fields
+            .iter()
+            .map(|field| (field.key().to_string(), json[field.key()].clone()))
+            .collect(),
+    ))
+}
+
+/// Escapes backslashes, newlines and tabs so a value fits on one
+/// tab-separated line.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t")
+}
+
+fn porcelain_entry(entry: &Entry) -> String {
+    format!(
+        "{}\t{}\t{}\t{}\t{}",
+        entry.id,
+        entry.created_at.to_rfc3339(),
+        entry.pinned,
+        escape(&entry.content),
+        escape(&entry.tags.join(","))
+    )
+}
+
+fn csv_entries(entries: &[Entry]) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(CSV_HEADER)?;
+
+    for entry in entries {
+        writer.write_record([
+            entry.id.to_string(),
+            entry.created_at.to_rfc3339(),
+            entry
+                .updated_at
+                .map(|updated_at| updated_at.to_rfc3339())
+                .unwrap_or_default(),
+            entry.pinned.to_string(),
+            entry.starred.to_string(),
+            tags_cell(&entry.tags)?,
+            entry.category.clone().unwrap_or_default(),
+            entry
+                .metadata
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?
+                .unwrap_or_default(),
+            entry.content.clone(),
+        ])?;
+    }
+
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Tags as a JSON array in a single CSV cell, so tags containing commas
+/// survive a round trip. Entries without tags get an empty cell.
+fn tags_cell(tags: &[String]) -> Result<String> {
+    if tags.is_empty() {
+        return Ok(String::new());
+    }
+
+    Ok(serde_json::to_string(tags)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn excerpt_with_a_huge_context_keeps_the_whole_content() {
+        let content = "one\ntwo match\nthree";
+        let pattern = Regex::new("match").unwrap();
+
+        assert_eq!(excerpt(content, &pattern, usize::MAX), content);
+    }
+}
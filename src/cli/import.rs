@@ -0,0 +1,234 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use csv::Position;
+use diary_core::models::NewEntry;
+use serde_json::value::RawValue;
+
+const TEXT_DELIMITER: &str = "---";
+
+/// Parses an import file. Files with a `.csv` extension are read as CSV
+/// dumps, anything else as either a JSON array of entries or plain text with
+/// entries separated by `---` lines. Returns one result per row, in file
+/// order, with the line of the file the row starts on. Only problems with
+/// the file as a whole, such as a missing CSV column, are returned as an
+/// error.
+pub fn parse_rows(path: &Path, text: &str) -> Result<Vec<(u64, Result<NewEntry>)>> {
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+    {
+        return parse_csv(text);
+    }
+
+    if text.trim_start().starts_with('[') {
+        let rows: Vec<&RawValue> =
+            serde_json::from_str(text).context("Failed to parse JSON import file")?;
+        return Ok(rows
+            .into_iter()
+            .map(|row| {
+                let line = line_of(text, row.get());
+                let entry = serde_json::from_str(row.get())
+                    .with_context(|| format!("Invalid entry on line {}", line));
+                (line, entry)
+            })
+            .collect());
+    }
+
+    Ok(split_text(text)
+        .into_iter()
+        .map(|(line, content)| {
+            let entry = NewEntry {
+                content,
+                pinned: false,
+                starred: false,
+                tags: Vec::new(),
+                created_at: None,
+                metadata: None,
+                category: None,
+            };
+            (line, Ok(entry))
+        })
+        .collect())
+}
+
+/// Reads rows in the `--format csv` dump layout. Ids and `updated_at` are
+/// assigned by the database, `created_at` is kept from the file. The
+/// `starred`, `tags`, `category` and `metadata` columns are optional, so
+/// dumps from before they existed still import. Empty cells leave the
+/// field unset.
+fn parse_csv(text: &str) -> Result<Vec<(u64, Result<NewEntry>)>> {
+    let mut reader = csv::Reader::from_reader(text.as_bytes());
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .with_context(|| format!("CSV file has no {} column", name))
+    };
+    let created_at = column("created_at")?;
+    let pinned = column("pinned")?;
+    let content = column("content")?;
+    let starred = headers.iter().position(|header| header == "starred");
+    let tags = headers.iter().position(|header| header == "tags");
+    let category = headers.iter().position(|header| header == "category");
+    let metadata = headers.iter().position(|header| header == "metadata");
+
+    Ok(reader
+        .records()
+        .map(|record| {
+            let position = match &record {
+                Ok(record) => record.position(),
+                Err(e) => e.position(),
+            };
+            let line = position.map_or(0, Position::line);
+
+            let entry = record
+                .with_context(|| format!("Invalid CSV on line {}", line))
+                .and_then(|record| {
+                    let created_at = DateTime::parse_from_rfc3339(&record[created_at])
+                        .with_context(|| format!("Invalid created_at on line {}", line))?
+                        .with_timezone(&Utc);
+
+                    Ok(NewEntry {
+                        content: record[content].to_string(),
+                        pinned: record[pinned]
+                            .parse()
+                            .with_context(|| format!("Invalid pinned value on line {}", line))?,
+                        starred: match starred {
+                            Some(starred) => record[starred].parse().with_context(|| {
+                                format!("Invalid starred value on line {}", line)
+                            })?,
+                            None => false,
+                        },
+                        tags: match tags.map(|tags| &record[tags]) {
+                            Some(tags) if !tags.is_empty() => serde_json::from_str(tags)
+                                .with_context(|| format!("Invalid tags on line {}", line))?,
+                            _ => Vec::new(),
+                        },
+                        created_at: Some(created_at),
+                        metadata: match metadata.map(|metadata| &record[metadata]) {
+                            Some(metadata) if !metadata.is_empty() => {
+                                Some(serde_json::from_str(metadata).with_context(|| {
+                                    format!("Invalid metadata on line {}", line)
+                                })?)
+                            }
+                            _ => None,
+                        },
+                        category: category
+                            .map(|category| record[category].to_string())
+                            .filter(|category| !category.is_empty()),
+                    })
+                });
+
+            (line, entry)
+        })
+        .collect())
+}
+
+/// Splits plain text on `---` lines. Each entry is paired with the line of
+/// its first non-blank line, so a report points at the text itself rather
+/// than at the delimiter above it.
+fn split_text(text: &str) -> Vec<(u64, String)> {
+    let mut chunks = Vec::new();
+    let mut current = Vec::new();
+    let mut start = None;
+
+    for (i, line) in text.lines().enumerate() {
+        if line.trim() == TEXT_DELIMITER {
+            if let Some(start) = start.take() {
+                chunks.push((start, current.join("\n")));
+            }
+            current.clear();
+        } else {
+            if start.is_none() && !line.trim().is_empty() {
+                start = Some(i as u64 + 1);
+            }
+            current.push(line);
+        }
+    }
+    if let Some(start) = start {
+        chunks.push((start, current.join("\n")));
+    }
+
+    chunks
+        .into_iter()
+        .map(|(start, chunk)| (start, chunk.trim().to_string()))
+        .collect()
+}
+
+/// The 1-based line of `text` on which `part`, a slice borrowed from it,
+/// starts.
+fn line_of(text: &str, part: &str) -> u64 {
+    let offset = part.as_ptr() as usize - text.as_ptr() as usize;
+    text[..offset].matches('\n').count() as u64 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(rows: Vec<(u64, Result<NewEntry>)>) -> Vec<u64> {
+        rows.into_iter().map(|(line, _)| line).collect()
+    }
+
+    fn parse_entries(path: &str, text: &str) -> Vec<NewEntry> {
+        parse_rows(Path::new(path), text)
+            .unwrap()
+            .into_iter()
+            .map(|(_, row)| row.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn text_entries_start_on_their_first_non_blank_line() {
+        let text = "first\n---\n\nsecond\nmore\n---\n---\nthird\n";
+        let rows = parse_rows(Path::new("notes.txt"), text).unwrap();
+        assert_eq!(lines(rows), vec![1, 4, 8]);
+    }
+
+    #[test]
+    fn csv_failures_report_the_file_line_after_multiline_content() {
+        let text = "id,created_at,updated_at,pinned,starred,content\n\
+                    1,2024-01-01T00:00:00Z,,false,false,\"two\nlines\"\n\
+                    2,not-a-date,,false,false,bad\n";
+        let rows = parse_rows(Path::new("dump.csv"), text).unwrap();
+        let (line, entry) = &rows[1];
+        assert_eq!(*line, 4);
+        assert!(format!("{:#}", entry.as_ref().unwrap_err()).contains("on line 4"));
+    }
+
+    #[test]
+    fn csv_tags_are_read_from_an_optional_json_column() {
+        let text = "created_at,pinned,tags,content\n\
+                    2024-01-01T00:00:00Z,false,\"[\"\"work\"\",\"\"a, b\"\"]\",tagged\n\
+                    2024-01-02T00:00:00Z,false,,untagged\n";
+        let rows = parse_entries("dump.csv", text);
+        assert_eq!(rows[0].tags, vec!["work", "a, b"]);
+        assert!(rows[1].tags.is_empty());
+
+        let text = "created_at,pinned,content\n2024-01-01T00:00:00Z,true,old dump\n";
+        let rows = parse_entries("dump.csv", text);
+        assert!(rows[0].tags.is_empty());
+    }
+
+    #[test]
+    fn csv_category_and_metadata_are_optional_columns() {
+        let text = "created_at,pinned,category,metadata,content\n\
+                    2024-01-01T00:00:00Z,false,work,\"{\"\"mood\"\":5}\",full\n\
+                    2024-01-02T00:00:00Z,false,,,bare\n";
+        let rows = parse_entries("dump.csv", text);
+        assert_eq!(rows[0].category.as_deref(), Some("work"));
+        assert_eq!(rows[0].metadata, Some(serde_json::json!({ "mood": 5 })));
+        assert_eq!(rows[1].category, None);
+        assert_eq!(rows[1].metadata, None);
+    }
+
+    #[test]
+    fn json_rows_start_on_their_opening_brace() {
+        let text = "[\n  {\"content\": \"a\"},\n\n  {\"content\": 5}\n]";
+        let rows = parse_rows(Path::new("entries.json"), text).unwrap();
+        assert_eq!(lines(rows), vec![2, 4]);
+    }
+}
@@ -1,2 +1,15 @@
 mod cli;
-pub use cli::{process_args, Args};
+mod color;
+mod date;
+mod editor;
+mod error;
+mod export;
+mod import;
+mod openapi;
+mod server;
+mod template;
+pub use cli::{init_config, process_args, Args, Mode};
+pub use error::{
+    exit_code, invalid_config, print_error, ErrorFormat, Unreachable, EXIT_INTERRUPTED,
+    EXIT_INVALID_INPUT,
+};
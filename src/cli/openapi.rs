@@ -0,0 +1,204 @@
+use serde_json::{json, Value};
+
+/// OpenAPI 3 description of the `/entries` API served by `serve`. Kept by
+/// hand next to the handlers, so a change to a request body or query
+/// parameter in server.rs has to be mirrored here.
+pub fn document() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Diary API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": {
+            "/entries": {
+                "get": {
+                    "summary": "List entries",
+                    "parameters": [
+                        query_param("page", integer(), "Page number, starting at 1."),
+                        query_param(
+                            "per_page",
+                            integer(),
+                            "Entries per page, capped by max_per_page.",
+                        ),
+                        query_param("sort", string_enum(&["asc", "desc"]), "Sort order."),
+                        query_param(
+                            "sort_by",
+                            string_enum(&["created-at", "updated-at", "content-length", "id"]),
+                            "Column to sort by.",
+                        ),
+                        query_param("pinned", boolean(), "Only pinned (true) or unpinned (false) entries."),
+                        query_param("starred", boolean(), "Only starred (true) or unstarred (false) entries."),
+                        query_param("substr", string(), "Only entries containing this text."),
+                        query_param("whole_word", boolean(), "Match substr against whole words only."),
+                        query_param("category", string(), "Only entries in this category."),
+                    ],
+                    "responses": {
+                        "200": json_response("The entries.", json!({
+                            "type": "array",
+                            "items": entry_ref(),
+                        })),
+                        "400": error_response("Invalid query parameter."),
+                    },
+                },
+                "post": {
+                    "summary": "Create an entry",
+                    "requestBody": json_body("CreateEntry"),
+                    "responses": {
+                        "201": json_response("The created entry.", entry_ref()),
+                        "400": error_response("Invalid entry."),
+                    },
+                },
+            },
+            "/entries/{id}": {
+                "parameters": [{
+                    "name": "id",
+                    "in": "path",
+                    "required": true,
+                    "schema": integer(),
+                }],
+                "get": {
+                    "summary": "Read an entry",
+                    "responses": {
+                        "200": json_response("The entry.", entry_ref()),
+                        "404": error_response("No entry with this id."),
+                    },
+                },
+                "patch": {
+                    "summary": "Update an entry",
+                    "requestBody": json_body("UpdateEntry"),
+                    "responses": {
+                        "200": json_response("The updated entry.", entry_ref()),
+                        "400": error_response("Invalid entry."),
+                        "404": error_response("No entry with this id."),
+                        "409": error_response("The entry changed since `version` was read."),
+                    },
+                },
+                "delete": {
+                    "summary": "Delete an entry",
+                    "responses": {
+                        "204": { "description": "The entry was deleted." },
+                        "404": error_response("No entry with this id."),
+                    },
+                },
+            },
+        },
+        "components": {
+            "schemas": {
+                "Entry": {
+                    "type": "object",
+                    "required": ["id", "content", "pinned", "starred", "tags", "created_at"],
+                    "properties": {
+                        "id": integer(),
+                        "content": string(),
+                        "pinned": boolean(),
+                        "starred": boolean(),
+                        "tags": { "type": "array", "items": string() },
+                        "created_at": timestamp(),
+                        "updated_at": nullable(timestamp()),
+                        "metadata": { "type": "object", "nullable": true },
+                        "category": nullable(string()),
+                        "version": integer(),
+                    },
+                },
+                "CreateEntry": {
+                    "type": "object",
+                    "required": ["content"],
+                    "properties": {
+                        "content": string(),
+                        "pinned": boolean(),
+                        "starred": boolean(),
+                        "tags": { "type": "array", "items": string() },
+                        "created_at": timestamp(),
+                        "metadata": { "type": "object" },
+                        "category": string(),
+                    },
+                },
+                "UpdateEntry": {
+                    "type": "object",
+                    "properties": {
+                        "content": string(),
+                        "pinned": boolean(),
+                        "starred": boolean(),
+                        "metadata": { "type": "object" },
+                        "category": string(),
+                        "version": {
+                            "type": "integer",
+                            "description": "Fail with 409 unless the entry is still at this version.",
+                        },
+                        "touch": {
+                            "type": "boolean",
+                            "default": true,
+                            "description": "Set to false to keep updated_at unchanged.",
+                        },
+                    },
+                },
+                "Error": {
+                    "type": "object",
+                    "required": ["error"],
+                    "properties": { "error": string() },
+                },
+            },
+        },
+    })
+}
+
+fn integer() -> Value {
+    json!({ "type": "integer", "format": "int64" })
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+fn timestamp() -> Value {
+    json!({ "type": "string", "format": "date-time" })
+}
+
+fn string_enum(values: &[&str]) -> Value {
+    json!({ "type": "string", "enum": values })
+}
+
+fn nullable(mut schema: Value) -> Value {
+    schema["nullable"] = true.into();
+    schema
+}
+
+fn entry_ref() -> Value {
+    json!({ "$ref": "#/components/schemas/Entry" })
+}
+
+fn query_param(name: &str, schema: Value, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "query",
+        "schema": schema,
+        "description": description,
+    })
+}
+
+fn json_body(schema: &str) -> Value {
+    json!({
+        "required": true,
+        "content": {
+            "application/json": {
+                "schema": { "$ref": format!("#/components/schemas/{}", schema) },
+            },
+        },
+    })
+}
+
+fn json_response(description: &str, schema: Value) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema } },
+    })
+}
+
+fn error_response(description: &str) -> Value {
+    json_response(description, json!({ "$ref": "#/components/schemas/Error" }))
+}
@@ -0,0 +1,246 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Error, Result};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use diary_core::{
+    db::{DiaryDB, EntryFilter, SortField, SortOrder},
+    models::Entry,
+    DiaryError,
+};
+use log::info;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{
+    cli::{check_page, normalize_content, require_entry, ContentLimits, PageLimit},
+    error::{invalid_input, InvalidInput},
+    openapi,
+};
+
+#[derive(Clone)]
+struct AppState {
+    db: Arc<DiaryDB>,
+    page_limit: PageLimit,
+    content_limits: ContentLimits,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateEntryBody {
+    content: String,
+    #[serde(default)]
+    pinned: bool,
+    #[serde(default)]
+    starred: bool,
+    tags: Option<Vec<String>>,
+    created_at: Option<DateTime<Utc>>,
+    metadata: Option<Value>,
+    category: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateEntryBody {
+    content: Option<String>,
+    pinned: Option<bool>,
+    starred: Option<bool>,
+    metadata: Option<Value>,
+    version: Option<i64>,
+    category: Option<String>,
+    /// Set to false to keep updated_at unchanged.
+    #[serde(default = "default_touch")]
+    touch: bool,
+}
+
+fn default_touch() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    page: Option<i64>,
+    per_page: Option<i64>,
+    sort: Option<String>,
+    sort_by: Option<String>,
+    pinned: Option<bool>,
+    starred: Option<bool>,
+    substr: Option<String>,
+    #[serde(default)]
+    whole_word: bool,
+    category: Option<String>,
+}
+
+/// Error returned by the handlers, mapped to a JSON body and status code.
+struct ApiError(Error);
+
+impl<E: Into<Error>> From<E> for ApiError {
+    fn from(e: E) -> Self {
+        ApiError(e.into())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = if self.0.downcast_ref::<InvalidInput>().is_some() {
+            StatusCode::BAD_REQUEST
+        } else {
+            match self.0.downcast_ref::<DiaryError>() {
+                Some(DiaryError::NotFound { .. }) => StatusCode::NOT_FOUND,
+                Some(DiaryError::Conflict { .. }) => StatusCode::CONFLICT,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            }
+        };
+
+        let body = serde_json::json!({ "error": self.0.to_string() });
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Parses an optional query parameter using the same names as the CLI flag.
+fn parse_value<T: ValueEnum>(value: Option<String>, name: &str) -> Result<Option<T>> {
+    value
+        .map(|value| {
+            T::from_str(&value, true)
+                .map_err(|_| invalid_input(format!("Invalid {}: {}", name, value)))
+        })
+        .transpose()
+}
+
+async fn list_entries(
+    State(state): State<AppState>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Vec<Entry>>, ApiError> {
+    check_page(query.page)?;
+    let sort: Option<SortOrder> = parse_value(query.sort, "sort")?;
+    let sort_field: Option<SortField> = parse_value(query.sort_by, "sort_by")?;
+
+    let entries = state
+        .db
+        .db
+        .read_entries(
+            query.page,
+            Some(state.page_limit.resolve(query.per_page)?),
+            sort,
+            sort_field,
+            &EntryFilter {
+                pinned: query.pinned,
+                starred: query.starred,
+                substr: query.substr,
+                whole_word: query.whole_word,
+                category: query.category,
+                ..EntryFilter::default()
+            },
+        )
+        .await?;
+
+    Ok(Json(entries))
+}
+
+async fn create_entry(
+    State(state): State<AppState>,
+    Json(body): Json<CreateEntryBody>,
+) -> Result<(StatusCode, Json<Entry>), ApiError> {
+    let content = normalize_content(body.content)?;
+    state.content_limits.check(&content)?;
+
+    let entry = state
+        .db
+        .db
+        .create_entry(
+            content,
+            body.pinned,
+            body.starred,
+            body.tags,
+            body.created_at,
+            body.metadata,
+            body.category,
+        )
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(entry)))
+}
+
+async fn read_entry(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<Entry>, ApiError> {
+    Ok(Json(require_entry(&state.db, id).await?))
+}
+
+async fn update_entry(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(body): Json<UpdateEntryBody>,
+) -> Result<Json<Entry>, ApiError> {
+    let content = body.content.map(normalize_content).transpose()?;
+    if let Some(content) = &content {
+        state.content_limits.check(content)?;
+    }
+
+    let entry = state
+        .db
+        .db
+        .update_entry(
+            id,
+            content,
+            body.pinned,
+            body.starred,
+            body.metadata,
+            body.version,
+            body.touch,
+            body.category,
+        )
+        .await?;
+
+    Ok(Json(entry))
+}
+
+async fn delete_entry(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    state.db.db.delete_entry(id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn openapi_document() -> Json<Value> {
+    Json(openapi::document())
+}
+
+/// Serves CRUD over `/entries`, and its OpenAPI description at
+/// `/openapi.json`, until the process is interrupted.
+pub async fn serve(
+    db: Arc<DiaryDB>,
+    page_limit: PageLimit,
+    content_limits: ContentLimits,
+    bind: &str,
+) -> Result<()> {
+    let app = Router::new()
+        .route("/entries", get(list_entries).post(create_entry))
+        .route(
+            "/entries/:id",
+            get(read_entry).patch(update_entry).delete(delete_entry),
+        )
+        .route("/openapi.json", get(openapi_document))
+        .with_state(AppState {
+            db,
+            page_limit,
+            content_limits,
+        });
+
+    let listener = tokio::net::TcpListener::bind(bind)
+        .await
+        .with_context(|| format!("Failed to bind to {}", bind))?;
+    info!("Listening on {}", listener.local_addr()?);
+
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use std::{fs, io, path::PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+
+use super::error::invalid_input;
+
+const TEMPLATE_EXTENSION: &str = "md";
+
+/// Templates live in diary/templates inside the platform config directory,
+/// one `<name>.md` file each.
+fn template_dir() -> Result<PathBuf> {
+    dirs::config_dir()
+        .map(|dir| dir.join("diary").join("templates"))
+        .context("Could not determine the config directory for templates")
+}
+
+fn template_path(name: &str) -> Result<PathBuf> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(invalid_input(format!(
+            "Invalid template name '{}', use letters, digits, '-' and '_'.",
+            name
+        )));
+    }
+
+    Ok(template_dir()?.join(format!("{}.{}", name, TEMPLATE_EXTENSION)))
+}
+
+/// Loads a template and fills in its placeholders for the current time.
+pub fn load(name: &str) -> Result<String> {
+    let path = template_path(name)?;
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(invalid_input(format!(
+                "Template '{}' does not exist.",
+                name
+            )))
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+
+    Ok(render(&text, Local::now()))
+}
+
+/// Saves a template, replacing one with the same name.
+pub fn save(name: &str, text: &str) -> Result<PathBuf> {
+    let path = template_path(name)?;
+    fs::create_dir_all(template_dir()?).context("Failed to create the template directory")?;
+    fs::write(&path, text).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(path)
+}
+
+/// Names of the saved templates, sorted.
+pub fn list() -> Result<Vec<String>> {
+    let dir = template_dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+
+    let mut names = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|ext| ext == TEMPLATE_EXTENSION)
+        {
+            if let Some(name) = path.file_stem() {
+                names.push(name.to_string_lossy().into_owned());
+            }
+        }
+    }
+    names.sort();
+
+    Ok(names)
+}
+
+/// Substitutes `{{date}}` (YYYY-MM-DD) and `{{time}}` (HH:MM) with `now`.
+pub fn render(text: &str, now: DateTime<Local>) -> String {
+    text.replace("{{date}}", &now.format("%Y-%m-%d").to_string())
+        .replace("{{time}}", &now.format("%H:%M").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 3, 5, 7, 9, 0).unwrap()
+    }
+
+    #[test]
+    fn render_fills_in_the_date_and_time() {
+        assert_eq!(render("# {{date}}", now()), "# 2024-03-05");
+        assert_eq!(render("Woke up at {{time}}", now()), "Woke up at 07:09");
+    }
+
+    #[test]
+    fn render_replaces_every_occurrence_of_a_placeholder() {
+        assert_eq!(
+            render("{{date}} {{time}}\n{{date}}, {{time}}", now()),
+            "2024-03-05 07:09\n2024-03-05, 07:09"
+        );
+    }
+
+    #[test]
+    fn render_keeps_text_without_placeholders_unchanged() {
+        let text = "No placeholders, {{ date }} or {date} here.\n";
+        assert_eq!(render(text, now()), text);
+    }
+}
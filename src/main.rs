@@ -1,35 +1,168 @@
 mod cli;
-use anyhow::Result;
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+};
+
+use anyhow::{Context, Error};
 use clap::Parser;
-use cli::{process_args, Args};
+use cli::{
+    exit_code, init_config, invalid_config, print_error, process_args, Args, ErrorFormat, Mode,
+    Unreachable, EXIT_INTERRUPTED, EXIT_INVALID_INPUT,
+};
 use diary_core::{Config, DiaryDB, DEFAULT_DB_URL};
+use log::{warn, Level, LevelFilter};
+
+const DB_URL_ENV: &str = "DIARY_DB_URL";
+const CONFIG_FILE: &str = "config.ini";
+
+fn default_config() -> Config {
+    Config {
+        db_url: DEFAULT_DB_URL.to_string(),
+        max_connections: None,
+        min_connections: None,
+        acquire_timeout_secs: None,
+        max_per_page: None,
+        clamp_per_page: None,
+        display_timezone: None,
+        export_state_file: None,
+        default_per_page: None,
+        default_pinned: None,
+        max_content_bytes: None,
+        max_content_lines: None,
+        fuzzy_threshold: None,
+        retention_days: None,
+        read_only: None,
+        preview_chars: None,
+    }
+}
+
+/// The --config path, or diary/config.ini in the platform config directory
+/// ($XDG_CONFIG_HOME or ~/.config on Linux, ~/Library/Application Support on
+/// macOS, %APPDATA% on Windows). Falls back to the working directory when no
+/// home directory can be found.
+fn config_path(args: &Args) -> String {
+    match &args.config {
+        Some(path) => path.clone(),
+        None => dirs::config_dir()
+            .map(|dir| dir.join("diary").join(CONFIG_FILE))
+            .unwrap_or_else(|| PathBuf::from(CONFIG_FILE))
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
+
+/// Reports an error like a failed operation, honoring --error-format, and
+/// exits with its status.
+fn exit_with(e: &Error, format: ErrorFormat) -> ! {
+    print_error(e, format);
+    process::exit(exit_code(e));
+}
+
+/// Logs informational messages to stderr, at a level chosen by -q/-v.
+fn init_logger(args: &Args) {
+    let level = match (args.quiet, args.verbose) {
+        (true, _) => LevelFilter::Warn,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format(|buf, record| match record.level() {
+            Level::Info => writeln!(buf, "{}", record.args()),
+            level => writeln!(buf, "{}: {}", level, record.args()),
+        })
+        .init();
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+async fn main() {
+    // clap exits with 2 on usage errors, which is EXIT_NOT_FOUND here.
+    // --help and --version are reported as errors too but exit with 0.
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        Err(e) => {
+            let code = if e.use_stderr() {
+                EXIT_INVALID_INPUT
+            } else {
+                0
+            };
+            let _ = e.print();
+            process::exit(code);
+        }
+    };
+
+    init_logger(&args);
 
-    println!("{:?}", args);
+    let error_format = args.error_format;
+    let env_url = std::env::var(DB_URL_ENV).ok();
+    let config_file = config_path(&args);
 
-    let config = match Config::from_file(&args.config) {
+    if matches!(args.mode, Mode::Init) {
+        if let Err(e) = init_config(&config_file, args.force, args.dry_run, &mut io::stdout()) {
+            exit_with(&e, error_format);
+        }
+        return;
+    }
+
+    // A config file that exists but can't be loaded is always an error, so a
+    // typo never silently switches to a different database.
+    let mut config = match Config::from_file(&config_file, args.profile.as_deref()) {
         Ok(conf) => conf,
-        Err(e) => {
-            eprintln!("Failed to load config file: {}", e);
-            eprintln!(
-                "Make sure {} exists and has the correct format",
-                args.config
+        Err(e) if Path::new(&config_file).exists() => exit_with(
+            &invalid_config(format!("Invalid config file {}: {}", config_file, e)),
+            error_format,
+        ),
+        Err(_) if env_url.is_some() => default_config(),
+        Err(_) if args.use_default_config => {
+            warn!(
+                "Config file {} not found, default config is used",
+                config_file
             );
-            println!("Default config is used");
-            Config {
-                db_url: DEFAULT_DB_URL.to_string(),
-            }
+            default_config()
         }
+        Err(_) => exit_with(
+            &invalid_config(format!(
+                "Config file {} not found. Create it with `diary init`, set {} or pass \
+                 --use-default-config to use the default database.",
+                config_file, DB_URL_ENV
+            )),
+            error_format,
+        ),
     };
 
-    println!("{:?}", config);
-    let diary_db = DiaryDB::new(&config.db_url).await?;
+    if let Some(url) = env_url {
+        config.db_url = url;
+    }
 
-    process_args(&diary_db, args).await?;
+    let diary_db = match DiaryDB::from_config(&config).await.context(Unreachable) {
+        Ok(db) => Arc::new(db),
+        Err(e) => exit_with(&e, error_format),
+    };
+
+    let mut stdout = io::stdout();
 
+    // On Ctrl-C the operation future is dropped, which rolls back any
+    // transaction it had open before the pool is closed.
+    let result = tokio::select! {
+        result = process_args(&diary_db, args, &config, &mut stdout) => Some(result),
+        _ = tokio::signal::ctrl_c() => None,
+    };
     diary_db.db.close().await;
-    Ok(())
+
+    match result {
+        Some(Ok(())) => {}
+        Some(Err(e)) => {
+            print_error(&e, error_format);
+            process::exit(exit_code(&e));
+        }
+        None => {
+            eprintln!("Interrupted");
+            process::exit(EXIT_INTERRUPTED);
+        }
+    }
 }